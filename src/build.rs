@@ -1,112 +1,350 @@
 //! Builds an ENR from CLI params
 // use super::Enr;
+use crate::derive;
 use crate::enr_ext::{QUIC6_ENR_KEY, QUIC_ENR_KEY};
-use crate::eth2_ext::EnrForkId;
-use enr::CombinedKey;
-use ssz::Decode;
-use std::fs::File;
-use std::io::prelude::*;
-use std::net::{Ipv4Addr, Ipv6Addr};
-
-pub fn build(matches: &clap::ArgMatches) -> Result<(), &'static str> {
-    // Generate or import a key for the ENR
-    let key = {
-        let key_bytes = if let Some(priv_key) = matches.get_one::<String>("private-key") {
-            Some(hex::decode(priv_key).map_err(|_| "Invalid private key hex bytes")?)
-        } else if let Some(key_file) = matches.get_one::<String>("key-file") {
-            let mut file = File::open(key_file).map_err(|_| "Cannot find key-file")?;
-            let mut key_bytes: Vec<u8> = Vec::with_capacity(36);
-            file.read_to_end(&mut key_bytes)
-                .map_err(|_| "Cannot read key file")?;
-            Some(key_bytes)
-        } else {
-            None
+use crate::keys::{self, KeyType};
+use crate::local_addr;
+use crate::offline;
+use crate::seq::{self, SeqPolicy};
+use crate::signer::{RemoteSigner, Signer};
+use crate::spec::EnrSpec;
+use enr::{CombinedKey, EnrKey, EnrPublicKey};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use libp2p_identity::PeerId;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// The address fields of an ENR, collected from the individual CLI flags and `--listen-addr`
+/// multiaddrs.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Addresses {
+    pub ip4: Option<Ipv4Addr>,
+    pub ip6: Option<Ipv6Addr>,
+    pub tcp4: Option<u16>,
+    pub tcp6: Option<u16>,
+    pub udp4: Option<u16>,
+    pub udp6: Option<u16>,
+    pub quic4: Option<u16>,
+    pub quic6: Option<u16>,
+}
+
+/// Sets a field, failing if it already holds a different value.
+fn set_once<T: PartialEq>(
+    field: &mut Option<T>,
+    value: T,
+    error: &'static str,
+) -> Result<(), &'static str> {
+    match field {
+        Some(existing) if *existing != value => Err(error),
+        _ => {
+            *field = Some(value);
+            Ok(())
+        }
+    }
+}
+
+impl Addresses {
+    /// Maps a multiaddr of the form `/ip4|ip6/<ip>/tcp|udp/<port>[/quic-v1][/p2p/<peer-id>]` onto the
+    /// `ip`/`ip6`, `tcp`/`tcp6`, `udp`/`udp6` or `quic`/`quic6` fields. A `/p2p/` component must match
+    /// `peer_id`.
+    pub fn add_multiaddr(
+        &mut self,
+        multiaddr: &Multiaddr,
+        peer_id: &PeerId,
+    ) -> Result<(), &'static str> {
+        let mut protocols = multiaddr.iter();
+        let ip = match protocols.next() {
+            Some(Protocol::Ip4(ip)) => IpAddr::V4(ip),
+            Some(Protocol::Ip6(ip)) => IpAddr::V6(ip),
+            _ => return Err("Listen multiaddrs must start with /ip4 or /ip6"),
         };
+        let transport = protocols
+            .next()
+            .ok_or("Listen multiaddrs must specify a tcp or udp port")?;
 
-        if let Some(mut byte_key) = key_bytes {
-            if let Ok(combined_key) = CombinedKey::secp256k1_from_bytes(&mut byte_key) {
-                combined_key
-            } else if let Ok(combined_key) = CombinedKey::ed25519_from_bytes(&mut byte_key) {
-                combined_key
-            } else {
-                return Err("Invalid private key");
+        let mut is_quic = false;
+        for protocol in protocols {
+            match protocol {
+                Protocol::QuicV1 if matches!(transport, Protocol::Udp(_)) && !is_quic => {
+                    is_quic = true
+                }
+                Protocol::P2p(multiaddr_peer_id) => {
+                    if multiaddr_peer_id != *peer_id {
+                        return Err("The /p2p/ component of a listen multiaddr does not match the signing key");
+                    }
+                }
+                _ => return Err("Unsupported protocol in listen multiaddr"),
             }
-        } else {
-            CombinedKey::generate_secp256k1()
         }
-    };
 
-    // Build the ENR:
-    let mut enr_builder = enr::Builder::default();
+        let (ip4, ip6) = match ip {
+            IpAddr::V4(ip) => {
+                set_once(&mut self.ip4, ip, "Conflicting IPv4 addresses")?;
+                (true, false)
+            }
+            IpAddr::V6(ip) => {
+                set_once(&mut self.ip6, ip, "Conflicting IPv6 addresses")?;
+                (false, true)
+            }
+        };
 
-    if let Some(seq) = matches.get_one::<String>("seq") {
-        enr_builder.seq(seq.parse::<u64>().map_err(|_| "Invalid sequence number")?);
-    }
-    if let Some(ip) = matches.get_one::<String>("ip") {
-        let ipv4 = ip.parse::<Ipv4Addr>().map_err(|_| "Invalid IPv4 address")?;
-        enr_builder.ip4(ipv4);
-    }
-    if let Some(ip6) = matches.get_one::<String>("ip6") {
-        let ipv6 = ip6
-            .parse::<Ipv6Addr>()
-            .map_err(|_| "Invalid IPv6 address")?;
-        enr_builder.ip6(ipv6);
+        match (transport, is_quic) {
+            (Protocol::Tcp(port), _) if ip4 => {
+                set_once(&mut self.tcp4, port, "Conflicting tcp ports")
+            }
+            (Protocol::Tcp(port), _) if ip6 => {
+                set_once(&mut self.tcp6, port, "Conflicting tcp6 ports")
+            }
+            (Protocol::Udp(port), false) if ip4 => {
+                set_once(&mut self.udp4, port, "Conflicting udp ports")
+            }
+            (Protocol::Udp(port), false) if ip6 => {
+                set_once(&mut self.udp6, port, "Conflicting udp6 ports")
+            }
+            (Protocol::Udp(port), true) if ip4 => {
+                set_once(&mut self.quic4, port, "Conflicting quic ports")
+            }
+            (Protocol::Udp(port), true) if ip6 => {
+                set_once(&mut self.quic6, port, "Conflicting quic6 ports")
+            }
+            _ => Err("Listen multiaddrs must specify a tcp or udp port"),
+        }
     }
 
-    if let Some(tcp) = matches.get_one::<String>("tcp-port") {
-        enr_builder.tcp4(tcp.parse::<u16>().map_err(|_| "Invalid tcp port")?);
-    }
-    if let Some(tcp6) = matches.get_one::<String>("tcp6-port") {
-        enr_builder.tcp6(tcp6.parse::<u16>().map_err(|_| "Invalid tcp6 port")?);
+    /// Adds the collected fields to an ENR builder.
+    pub fn apply(&self, enr_builder: &mut enr::Builder<CombinedKey>) {
+        if let Some(ip4) = self.ip4 {
+            enr_builder.ip4(ip4);
+        }
+        if let Some(ip6) = self.ip6 {
+            enr_builder.ip6(ip6);
+        }
+        if let Some(tcp4) = self.tcp4 {
+            enr_builder.tcp4(tcp4);
+        }
+        if let Some(tcp6) = self.tcp6 {
+            enr_builder.tcp6(tcp6);
+        }
+        if let Some(udp4) = self.udp4 {
+            enr_builder.udp4(udp4);
+        }
+        if let Some(udp6) = self.udp6 {
+            enr_builder.udp6(udp6);
+        }
+        if let Some(quic4) = self.quic4 {
+            enr_builder.add_value(QUIC_ENR_KEY, &quic4);
+        }
+        if let Some(quic6) = self.quic6 {
+            enr_builder.add_value(QUIC6_ENR_KEY, &quic6);
+        }
     }
+}
 
-    if let Some(udp) = matches.get_one::<String>("udp-port") {
-        enr_builder.udp4(udp.parse::<u16>().map_err(|_| "Invalid udp port")?);
-    }
-    if let Some(udp6) = matches.get_one::<String>("udp6-port") {
-        enr_builder.udp6(udp6.parse::<u16>().map_err(|_| "Invalid udp6 port")?);
-    }
+/// Parses an optional numeric flag.
+fn parse_flag<T: std::str::FromStr>(
+    matches: &clap::ArgMatches,
+    name: &str,
+    error: &'static str,
+) -> Result<Option<T>, &'static str> {
+    matches
+        .get_one::<String>(name)
+        .map(|value| value.parse::<T>().map_err(|_| error))
+        .transpose()
+}
 
-    if let Some(quic) = matches.get_one::<String>("quic-port") {
-        enr_builder.add_value(
-            QUIC_ENR_KEY,
-            &quic
-                .parse::<u16>()
-                .map_err(|_| "Invalid quic port")?
-                .to_be_bytes(),
-        );
-    }
-    if let Some(quic6) = matches.get_one::<String>("quic6-port") {
-        enr_builder.add_value(
-            QUIC6_ENR_KEY,
-            &quic6
-                .parse::<u16>()
-                .map_err(|_| "Invalid quic6 port")?
-                .to_be_bytes(),
-        );
+/// Collects the values given by CLI flags into a spec that can override a spec file.
+fn spec_from_matches(matches: &clap::ArgMatches) -> Result<EnrSpec, String> {
+    let mut fields = BTreeMap::new();
+    for field in matches.get_many::<String>("field").into_iter().flatten() {
+        let (key, typed_value) = field.split_once('=').ok_or("Fields take name=type:value")?;
+        fields.insert(key.to_string(), typed_value.to_string());
     }
+    Ok(EnrSpec {
+        seq: parse_flag(matches, "seq", "Invalid sequence number")?,
+        ip: local_addr::ip4_from_matches(matches)?,
+        ip6: local_addr::ip6_from_matches(matches)?,
+        tcp_port: parse_flag(matches, "tcp-port", "Invalid tcp port")?,
+        tcp6_port: parse_flag(matches, "tcp6-port", "Invalid tcp6 port")?,
+        udp_port: parse_flag(matches, "udp-port", "Invalid udp port")?,
+        udp6_port: parse_flag(matches, "udp6-port", "Invalid udp6 port")?,
+        quic_port: parse_flag(matches, "quic-port", "Invalid quic port")?,
+        quic6_port: parse_flag(matches, "quic6-port", "Invalid quic6 port")?,
+        listen_addrs: matches
+            .get_many::<String>("listen-addr")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        eth2: matches.get_one::<String>("eth2").cloned(),
+        fields,
+        seq_policy: SeqPolicy::from_matches(matches)?,
+        seq_state: matches.get_one::<String>("seq-state").map(PathBuf::from),
+        key_type: KeyType::from_matches(matches)?,
+        ..Default::default()
+    })
+}
 
-    if let Some(eth2) = matches.get_one::<String>("eth2") {
-        let eth2_bytes = hex::decode(eth2).map_err(|_| "Invalid eth2 hex bytes")?;
-        EnrForkId::from_ssz_bytes(&eth2_bytes).map_err(|_| "Invalid eth2 ssz bytes")?;
-
-        enr_builder.add_value("eth2", &eth2_bytes);
+pub fn build(matches: &clap::ArgMatches) -> Result<(), String> {
+    // Values given on the command line take precedence over the spec file.
+    let file_spec = match matches.get_one::<String>("spec") {
+        Some(path) => EnrSpec::from_file(Path::new(path))?,
+        None => EnrSpec::default(),
+    };
+    let mut spec = file_spec.merge(spec_from_matches(matches)?);
+    if let Some(pid) = matches.get_one::<String>("ports-from-listening") {
+        let pid = pid.parse::<u32>().map_err(|_| "Invalid process id")?;
+        local_addr::fill_ports(&mut spec, &local_addr::listening_ports(pid)?)?;
     }
+    // The record is signed elsewhere with --unsigned, by a remote signer which keeps the key, or
+    // by a generated or imported key.
+    let remote_signer = matches
+        .get_one::<String>("signer-url")
+        .map(|url| RemoteSigner::new(url));
+    let local_key = if matches.get_flag("unsigned") || remote_signer.is_some() {
+        None
+    } else {
+        let loaded_key = match (
+            keys::load_key_as(matches, spec.key_type)?,
+            derive::key_from_matches(matches, spec.key_type)?,
+            &spec.key_file,
+        ) {
+            (Some(key), _, _) | (None, Some(key), _) => Some(key),
+            (None, None, Some(key_file)) => Some(keys::read_key_file(key_file, spec.key_type)?),
+            (None, None, None) => None,
+        };
+        let is_generated = loaded_key.is_none();
+        let key =
+            loaded_key.unwrap_or_else(|| spec.key_type.unwrap_or(KeyType::Secp256k1).generate());
+        Some((key, is_generated))
+    };
+    let public_key = match (&local_key, &remote_signer) {
+        (Some((key, _)), _) => key.public(),
+        (None, Some(signer)) => signer.public_key()?,
+        (None, None) => offline::parse_public_key(
+            matches
+                .get_one::<String>("public-key")
+                .ok_or("--unsigned requires --public-key")?,
+        )?,
+    };
 
-    let enr = match enr_builder.build(&key) {
-        Err(e) => {
-            println!("Failed to build ENR: {:?}", e);
-            return Err("Failed to build ENR");
-        }
-        Ok(v) => v,
+    // A record replacing an earlier version needs a higher sequence number. An existing --enr-out
+    // file of the same key is taken to be the earlier version.
+    let previous_enr = match matches.get_one::<String>("previous-enr") {
+        Some(previous_enr) => Some(seq::read_previous_enr(previous_enr)?),
+        None => matches.get_one::<String>("enr-out").and_then(|enr_out| {
+            let previous_enr = seq::read_previous_enr(enr_out).ok()?;
+            if previous_enr.public_key().encode() != public_key.encode() {
+                println!(
+                    "Warning: {} belongs to a different key, its sequence number is not continued",
+                    enr_out
+                );
+                return None;
+            }
+            Some(previous_enr)
+        }),
     };
+    spec.seq = seq::next_seq(
+        spec.seq_policy,
+        spec.seq_state.as_deref(),
+        previous_enr.map(|enr| enr.seq()),
+        spec.seq,
+        Some(SeqPolicy::Increment),
+    )?;
+
+    let enr = match (&local_key, &remote_signer) {
+        (Some((key, _)), _) => spec.build(key, matches.get_flag("force"))?,
+        (None, Some(signer)) => signer.sign(
+            &offline::placeholder_record(&spec, &public_key, matches.get_flag("force"))?,
+            &public_key,
+        )?,
+        (None, None) => return offline::build_unsigned(matches, &spec, public_key),
+    };
+
+    if let (Some(key_out), Some((key, _))) = (matches.get_one::<String>("key-out"), &local_key) {
+        // The raw key bytes, as in a Lighthouse `key.dat`.
+        keys::write_secret_file(Path::new(key_out), &Zeroizing::new(key.encode()))?;
+    }
+    if let Some(enr_out) = matches.get_one::<String>("enr-out") {
+        // Lighthouse stores the base64 record as text in `enr.dat`.
+        std::fs::write(enr_out, enr.to_base64()).map_err(|_| "Cannot write ENR file")?;
+    }
+    seq::save_state(spec.seq_state.as_deref(), enr.seq())?;
 
     println!("Built ENR: {}", enr.to_base64());
     println!();
-    println!("Private Key: {}", hex::encode(key.encode()));
-    println!();
+    match local_key {
+        Some((key, _)) if matches.get_flag("print-secret") => {
+            println!("Private Key: {}", hex::encode(key.encode()));
+            println!();
+        }
+        Some((_, true)) if !matches.contains_id("key-out") => {
+            println!(
+                "Note: the generated key was not saved, use --key-out or --print-secret to keep it"
+            );
+            println!();
+        }
+        _ => {}
+    }
 
-    super::print_enr(enr);
+    crate::output::print_enr(enr);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enr_ext::CombinedKeyPublicExt;
+    use enr::EnrKey;
+
+    #[test]
+    fn test_listen_addrs_map_to_fields() {
+        let key = CombinedKey::generate_secp256k1();
+        let peer_id = key.public().as_peer_id();
+        let mut addresses = Addresses::default();
+        for multiaddr in [
+            "/ip4/1.2.3.4/tcp/9000".to_string(),
+            "/ip4/1.2.3.4/udp/9000".to_string(),
+            format!("/ip6/::1/udp/9001/quic-v1/p2p/{}", peer_id),
+        ] {
+            addresses
+                .add_multiaddr(&multiaddr.parse().unwrap(), &peer_id)
+                .unwrap();
+        }
+        assert_eq!(
+            addresses,
+            Addresses {
+                ip4: Some("1.2.3.4".parse().unwrap()),
+                ip6: Some("::1".parse().unwrap()),
+                tcp4: Some(9000),
+                udp4: Some(9000),
+                quic6: Some(9001),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_listen_addrs_reject_conflicts() {
+        let key = CombinedKey::generate_secp256k1();
+        let peer_id = key.public().as_peer_id();
+        let mut addresses = Addresses::default();
+        addresses
+            .add_multiaddr(&"/ip4/1.2.3.4/tcp/9000".parse().unwrap(), &peer_id)
+            .unwrap();
+        assert!(addresses
+            .add_multiaddr(&"/ip4/5.6.7.8/udp/9000".parse().unwrap(), &peer_id)
+            .is_err());
+        assert!(addresses
+            .add_multiaddr(&"/ip4/1.2.3.4/tcp/9001".parse().unwrap(), &peer_id)
+            .is_err());
+
+        let other_peer_id = CombinedKey::generate_secp256k1().public().as_peer_id();
+        let multiaddr = format!("/ip4/1.2.3.4/udp/9000/p2p/{}", other_peer_id);
+        assert!(addresses
+            .add_multiaddr(&multiaddr.parse().unwrap(), &peer_id)
+            .is_err());
+    }
+}
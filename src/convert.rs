@@ -0,0 +1,287 @@
+//! Converts between the different representations of a node identity.
+use crate::enode::Enode;
+use crate::enr_ext::{peer_id_to_public_key, CombinedKeyPublicExt, EnrExt};
+use crate::keys::KeyType;
+use crate::output::OutputFormat;
+use crate::Enr;
+use enr::{CombinedPublicKey, EnrPublicKey, NodeId};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use libp2p_identity::PeerId;
+use serde_json::{json, Value};
+use std::io::prelude::*;
+
+/// The multicodec of a libp2p public key, used in CIDv1 peer ids.
+const LIBP2P_KEY_CODEC: u8 = 0x72;
+
+/// A node identity parsed from any of its representations.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// The public key of the node.
+    pub public_key: CombinedPublicKey,
+    /// The signed record, if the input was an ENR.
+    pub enr: Option<Enr>,
+    /// The enode, if the input was an enode URL or a secp256k1 ENR.
+    pub enode: Option<Enode>,
+    /// Known addresses of the node, without the `/p2p/` suffix.
+    pub multiaddrs: Vec<Multiaddr>,
+}
+
+impl Identity {
+    fn from_public_key(public_key: CombinedPublicKey) -> Self {
+        let enode = match &public_key {
+            CombinedPublicKey::Secp256k1(pk) => Some(Enode {
+                public_key: *pk,
+                ip: None,
+                tcp: None,
+                udp: None,
+            }),
+            CombinedPublicKey::Ed25519(_) => None,
+        };
+        Identity {
+            public_key,
+            enr: None,
+            enode,
+            multiaddrs: Vec::new(),
+        }
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        NodeId::from(self.public_key.clone())
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.public_key.as_peer_id()
+    }
+
+    /// The known multiaddrs with the `PeerId` appended.
+    pub fn multiaddrs_p2p(&self) -> Vec<Multiaddr> {
+        let peer_id = self.peer_id();
+        self.multiaddrs
+            .iter()
+            .cloned()
+            .map(|mut multiaddr| {
+                multiaddr.push(Protocol::P2p(peer_id));
+                multiaddr
+            })
+            .collect()
+    }
+}
+
+/// Encodes a peer id as a base32 CIDv1 with the `libp2p-key` codec.
+pub fn peer_id_to_cid(peer_id: &PeerId) -> String {
+    let mut bytes = vec![1u8, LIBP2P_KEY_CODEC];
+    bytes.extend(peer_id.to_bytes());
+    multibase::encode(multibase::Base::Base32Lower, bytes)
+}
+
+/// Parses a peer id in either base58 or CIDv1 form.
+pub fn parse_peer_id(input: &str) -> Result<PeerId, &'static str> {
+    if let Ok(peer_id) = input.parse::<PeerId>() {
+        return Ok(peer_id);
+    }
+    let (_, bytes) = multibase::decode(input).map_err(|_| "Invalid peer id")?;
+    match bytes.as_slice() {
+        [1, LIBP2P_KEY_CODEC, multihash @ ..] => {
+            PeerId::from_bytes(multihash).map_err(|_| "Invalid CIDv1 peer id multihash")
+        }
+        _ => Err("CID is not a CIDv1 libp2p-key"),
+    }
+}
+
+/// Parses a hex encoded secp256k1 public key in compressed (33 bytes) or uncompressed (64 or 65
+/// bytes) form.
+fn parse_public_key_hex(input: &str) -> Result<CombinedPublicKey, &'static str> {
+    let mut bytes = hex::decode(input.trim_start_matches("0x")).map_err(|_| "Invalid hex")?;
+    match bytes.len() {
+        32 => return Err("32 bytes is ambiguous: node ids cannot be converted and ed25519 keys should be given as a peer id"),
+        64 => bytes.insert(0, 4),
+        33 | 65 => {}
+        _ => return Err("Public key must be 33, 64 or 65 bytes"),
+    }
+    enr::k256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes)
+        .map(CombinedPublicKey::from)
+        .map_err(|_| "Invalid secp256k1 public key")
+}
+
+/// Parses any supported representation of a node identity: an ENR, an enode URL, a `/p2p/`
+/// multiaddr, a peer id (base58 or CIDv1) or a hex encoded secp256k1 public key.
+pub fn parse_identity(input: &str) -> Result<Identity, &'static str> {
+    let input = input.trim();
+    if input.starts_with("enr:") {
+        let enr = input.parse::<Enr>().map_err(|_| "Invalid ENR")?;
+        let mut identity = Identity::from_public_key(enr.public_key());
+        identity.enode = Enode::from_enr(&enr);
+        identity.multiaddrs = enr.multiaddr();
+        identity.enr = Some(enr);
+        Ok(identity)
+    } else if input.starts_with("enode://") {
+        let enode = input.parse::<Enode>()?;
+        let mut identity = Identity::from_public_key(enode.combined_public_key());
+        identity.multiaddrs = enode.multiaddr();
+        identity.enode = Some(enode);
+        Ok(identity)
+    } else if input.starts_with('/') {
+        let multiaddr = input
+            .parse::<Multiaddr>()
+            .map_err(|_| "Invalid multiaddr")?;
+        let peer_id = multiaddr
+            .iter()
+            .find_map(|protocol| match protocol {
+                Protocol::P2p(peer_id) => Some(peer_id),
+                _ => None,
+            })
+            .ok_or("Multiaddr has no /p2p/ component")?;
+        let public_key =
+            peer_id_to_public_key(&peer_id).map_err(|_| "Peer id has no public key")?;
+        let mut identity = Identity::from_public_key(public_key);
+        let address: Multiaddr = multiaddr
+            .iter()
+            .filter(|protocol| !matches!(protocol, Protocol::P2p(_)))
+            .collect();
+        if !address.is_empty() {
+            identity.multiaddrs.push(address);
+        }
+        Ok(identity)
+    } else if input
+        .trim_start_matches("0x")
+        .chars()
+        .all(|c| c.is_ascii_hexdigit())
+    {
+        parse_public_key_hex(input).map(Identity::from_public_key)
+    } else {
+        let peer_id = parse_peer_id(input)?;
+        let public_key =
+            peer_id_to_public_key(&peer_id).map_err(|_| "Peer id has no public key")?;
+        Ok(Identity::from_public_key(public_key))
+    }
+}
+
+fn print_identity(input: &str, identity: &Identity) {
+    println!("Input: {}", input);
+    println!("Key Type: {}", KeyType::of(&identity.public_key));
+    println!("NodeId: {}", hex::encode(identity.node_id().raw()));
+    println!("Libp2p PeerId: {}", identity.peer_id());
+    println!(
+        "Libp2p PeerId (CIDv1): {}",
+        peer_id_to_cid(&identity.peer_id())
+    );
+    println!("Public Key: {}", hex::encode(identity.public_key.encode()));
+    if let CombinedPublicKey::Secp256k1(_) = identity.public_key {
+        println!(
+            "Public Key (uncompressed): {}",
+            hex::encode(&identity.public_key.encode_uncompressed()[1..])
+        );
+    }
+    if let Some(enode) = &identity.enode {
+        println!("EnodeId: {}", enode);
+    }
+    if let Some(enr) = &identity.enr {
+        println!("ENR: {}", enr.to_base64());
+    }
+    let multiaddrs = identity.multiaddrs_p2p();
+    if !multiaddrs.is_empty() {
+        println!("Known multiaddrs:");
+        for multiaddr in multiaddrs {
+            println!("{}", multiaddr);
+        }
+    }
+}
+
+fn identity_json(input: &str, identity: &Identity) -> Value {
+    let mut value = json!({
+        "input": input,
+        "key_type": KeyType::of(&identity.public_key).to_string(),
+        "node_id": hex::encode(identity.node_id().raw()),
+        "peer_id": identity.peer_id().to_string(),
+        "peer_id_cid": peer_id_to_cid(&identity.peer_id()),
+        "public_key": hex::encode(identity.public_key.encode()),
+        "multiaddrs": identity
+            .multiaddrs_p2p()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    });
+    let object = value.as_object_mut().expect("is an object");
+    if let CombinedPublicKey::Secp256k1(_) = identity.public_key {
+        object.insert(
+            "public_key_uncompressed".into(),
+            json!(hex::encode(&identity.public_key.encode_uncompressed()[1..])),
+        );
+    }
+    if let Some(enode) = &identity.enode {
+        object.insert("enode".into(), json!(enode.to_string()));
+    }
+    if let Some(enr) = &identity.enr {
+        object.insert("enr".into(), json!(enr.to_base64()));
+    }
+    value
+}
+
+pub fn convert(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let format = OutputFormat::from_matches(matches)?;
+
+    let mut inputs: Vec<String> = matches
+        .get_many::<String>("input")
+        .map(|inputs| inputs.filter(|input| *input != "-").cloned().collect())
+        .unwrap_or_default();
+    let read_stdin = matches
+        .get_many::<String>("input")
+        .is_none_or(|mut inputs| inputs.any(|input| input == "-"));
+    if read_stdin {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|_| "Cannot read stdin")?;
+        inputs.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+
+    for (i, input) in inputs.iter().enumerate() {
+        let identity = parse_identity(input);
+        match (format, identity) {
+            (OutputFormat::Json, Ok(identity)) => println!("{}", identity_json(input, &identity)),
+            (OutputFormat::Json, Err(e)) => println!("{}", json!({ "input": input, "error": e })),
+            (_, Ok(identity)) => {
+                if i > 0 {
+                    println!();
+                }
+                print_identity(input, &identity);
+            }
+            (_, Err(e)) => println!("Failed to convert {}: {}", input, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENR: &str = "enr:-Ku4QJsxkOibTc9FXfBWYmcdMAGwH4bnOOFb4BlTHfMdx_f0WN-u4IUqZcQVP9iuEyoxipFs7-Qd_rH_0HfyOQitc7IBh2F0dG5ldHOIAAAAAAAAAACEZXRoMpD1pf1CAAAAAP__________gmlkgnY0gmlwhLAJM9iJc2VjcDI1NmsxoQL2RyM26TKZzqnUsyycHQB4jnyg6Wi79rwLXtaZXty06YN1ZHCCW8w";
+    const PEER_ID: &str = "16Uiu2HAmC13Brucnz5qR8caKi8qKK6766PFoxsF5MzK2RvbTyBRr";
+
+    #[test]
+    fn test_all_representations_agree() {
+        let from_enr = parse_identity(ENR).unwrap();
+        let node_id = from_enr.node_id();
+        let public_key = hex::encode(from_enr.public_key.encode());
+        let enode = from_enr.enode.clone().unwrap().to_string();
+        let cid = peer_id_to_cid(&from_enr.peer_id());
+
+        for input in [
+            PEER_ID.to_string(),
+            cid,
+            enode,
+            public_key,
+            format!("/ip4/176.9.51.216/udp/23500/p2p/{}", PEER_ID),
+        ] {
+            let identity = parse_identity(&input).unwrap();
+            assert_eq!(identity.node_id(), node_id, "{}", input);
+            assert_eq!(identity.peer_id().to_string(), PEER_ID, "{}", input);
+        }
+    }
+}
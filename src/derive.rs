@@ -0,0 +1,105 @@
+//! Derives node keys deterministically from a seed, so test networks get reproducible identities.
+//!
+//! Keys are expanded from the seed with HKDF-SHA256, salted with `DERIVATION_SALT`, using
+//! `<key-type>/<index>/<attempt>` as the info. The attempt counter only moves past 0 in the
+//! negligible case that the output is not a valid secp256k1 scalar.
+use crate::keys::KeyType;
+use enr::CombinedKey;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+const DERIVATION_SALT: &[u8] = b"enr-cli node key derivation";
+
+/// Seeds shorter than this are refused, they would make the derived keys guessable.
+const MIN_SEED_LENGTH: usize = 16;
+
+/// Parses a seed given as hex bytes or as a BIP-39 mnemonic, which is turned into its 64 byte seed
+/// with an empty passphrase.
+pub fn parse_seed(input: &str) -> Result<Zeroizing<Vec<u8>>, &'static str> {
+    let input = input.trim();
+    let seed = match hex::decode(input.trim_start_matches("0x")) {
+        Ok(seed) => Zeroizing::new(seed),
+        Err(_) => {
+            let mnemonic = bip39::Mnemonic::parse(input)
+                .map_err(|_| "The seed must be hex bytes or a valid BIP-39 mnemonic")?;
+            Zeroizing::new(mnemonic.to_seed("").to_vec())
+        }
+    };
+    if seed.len() < MIN_SEED_LENGTH {
+        return Err("The seed must be at least 16 bytes");
+    }
+    Ok(seed)
+}
+
+/// Derives the key of type `key_type` at `index`.
+pub fn derive_key(seed: &[u8], index: u32, key_type: KeyType) -> CombinedKey {
+    let hkdf = Hkdf::<Sha256>::new(Some(DERIVATION_SALT), seed);
+    (0u32..)
+        .find_map(|attempt| {
+            let mut key_bytes = Zeroizing::new([0u8; 32]);
+            let info = format!("{}/{}/{}", key_type, index, attempt);
+            hkdf.expand(info.as_bytes(), key_bytes.as_mut())
+                .expect("32 bytes is a valid HKDF output length");
+            key_type.key_from_bytes(key_bytes.as_mut()).ok()
+        })
+        .expect("a valid key is found")
+}
+
+/// Derives the key given by `--seed` and `--index`, if a seed is given.
+pub fn key_from_matches(
+    matches: &clap::ArgMatches,
+    key_type: Option<KeyType>,
+) -> Result<Option<CombinedKey>, &'static str> {
+    let Some(seed) = matches.get_one::<String>("seed") else {
+        return Ok(None);
+    };
+    let seed = parse_seed(seed)?;
+    let index = matches
+        .get_one::<String>("index")
+        .map(|index| index.parse::<u32>().map_err(|_| "Invalid key index"))
+        .transpose()?
+        .unwrap_or(0);
+    Ok(Some(derive_key(
+        &seed,
+        index,
+        key_type.unwrap_or(KeyType::Secp256k1),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::EnrKey;
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = parse_seed(mnemonic).unwrap();
+        // The BIP-39 test vector for an empty passphrase.
+        assert_eq!(hex::encode(&*seed), "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4");
+        assert!(parse_seed("0x00112233").is_err());
+
+        for key_type in [KeyType::Secp256k1, KeyType::Ed25519] {
+            let key = derive_key(&seed, 1, key_type);
+            assert_eq!(KeyType::of(&key.public()), key_type);
+            assert_eq!(key.encode(), derive_key(&seed, 1, key_type).encode());
+            assert_ne!(key.encode(), derive_key(&seed, 2, key_type).encode());
+        }
+
+        // Computed independently as HKDF-SHA256(salt, seed, "<key-type>/3/0").
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        for (key_type, secret) in [
+            (
+                KeyType::Secp256k1,
+                "b8f9db0f962c3c21377fd414bcc4f04d0d97a8a3340b37a89ba4da43f2b17055",
+            ),
+            (
+                KeyType::Ed25519,
+                "d2f265006fe54873a25a0ec4d59a19d930569541dc4a97d5db7a835d616530c2",
+            ),
+        ] {
+            assert_eq!(hex::encode(derive_key(&seed, 3, key_type).encode()), secret);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Detection, decoding and encoding of the ways an ENR can be serialized.
+use crate::Enr;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::str::FromStr;
+
+/// The serializations of an ENR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordEncoding {
+    /// URL-safe base64 with the `enr:` prefix, as defined in EIP-778.
+    Enr,
+    /// Base64 of the RLP, without the `enr:` prefix. Standard and URL-safe alphabets are accepted.
+    Base64,
+    /// Hex encoded RLP, with or without a `0x` prefix.
+    Hex,
+    /// Raw RLP bytes.
+    Rlp,
+}
+
+impl FromStr for RecordEncoding {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enr" => Ok(RecordEncoding::Enr),
+            "base64" => Ok(RecordEncoding::Base64),
+            "hex" => Ok(RecordEncoding::Hex),
+            "rlp" => Ok(RecordEncoding::Rlp),
+            _ => Err("Unknown record encoding"),
+        }
+    }
+}
+
+impl RecordEncoding {
+    /// The values accepted by the `--input-format` and `--reencode` CLI flags.
+    pub const VARIANTS: [&'static str; 4] = ["enr", "base64", "hex", "rlp"];
+
+    /// Guesses the encoding of a serialized record.
+    pub fn detect(bytes: &[u8]) -> RecordEncoding {
+        let Ok(text) = std::str::from_utf8(bytes) else {
+            return RecordEncoding::Rlp;
+        };
+        let text = text.trim();
+        if text.starts_with("enr:") {
+            RecordEncoding::Enr
+        } else if !text.is_empty()
+            && text
+                .trim_start_matches("0x")
+                .chars()
+                .all(|c| c.is_ascii_hexdigit())
+        {
+            // An RLP list of this size always starts with 0xf8 or 0xf9, which is never all hex in
+            // base64.
+            RecordEncoding::Hex
+        } else if text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_+/=".contains(c))
+        {
+            RecordEncoding::Base64
+        } else {
+            RecordEncoding::Rlp
+        }
+    }
+}
+
+/// Decodes a record, detecting the encoding if none is given.
+pub fn decode_record(bytes: &[u8], encoding: Option<RecordEncoding>) -> Result<Enr, &'static str> {
+    let encoding = encoding.unwrap_or_else(|| RecordEncoding::detect(bytes));
+    let text = || {
+        std::str::from_utf8(bytes)
+            .map(str::trim)
+            .map_err(|_| "Record is not valid text")
+    };
+    let rlp = match encoding {
+        RecordEncoding::Enr => return text()?.parse::<Enr>().map_err(|_| "Invalid ENR"),
+        RecordEncoding::Base64 => {
+            // Normalise the standard alphabet and any padding to URL-safe without padding.
+            let normalised: String = text()?
+                .trim_end_matches('=')
+                .chars()
+                .map(|c| match c {
+                    '+' => '-',
+                    '/' => '_',
+                    c => c,
+                })
+                .collect();
+            URL_SAFE_NO_PAD
+                .decode(normalised)
+                .map_err(|_| "Invalid base64")?
+        }
+        RecordEncoding::Hex => {
+            hex::decode(text()?.trim_start_matches("0x")).map_err(|_| "Invalid hex")?
+        }
+        RecordEncoding::Rlp => bytes.to_vec(),
+    };
+    alloy_rlp::Decodable::decode(&mut rlp.as_slice()).map_err(|_| "Invalid ENR RLP")
+}
+
+/// Encodes a record. Every encoding but [`RecordEncoding::Rlp`] produces text.
+pub fn encode_record(enr: &Enr, encoding: RecordEncoding) -> Vec<u8> {
+    match encoding {
+        RecordEncoding::Enr => enr.to_base64().into_bytes(),
+        RecordEncoding::Base64 => URL_SAFE_NO_PAD.encode(alloy_rlp::encode(enr)).into_bytes(),
+        RecordEncoding::Hex => hex::encode(alloy_rlp::encode(enr)).into_bytes(),
+        RecordEncoding::Rlp => alloy_rlp::encode(enr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENR: &str = "enr:-Ku4QJsxkOibTc9FXfBWYmcdMAGwH4bnOOFb4BlTHfMdx_f0WN-u4IUqZcQVP9iuEyoxipFs7-Qd_rH_0HfyOQitc7IBh2F0dG5ldHOIAAAAAAAAAACEZXRoMpD1pf1CAAAAAP__________gmlkgnY0gmlwhLAJM9iJc2VjcDI1NmsxoQL2RyM26TKZzqnUsyycHQB4jnyg6Wi79rwLXtaZXty06YN1ZHCCW8w";
+
+    #[test]
+    fn test_detect_and_round_trip_all_encodings() {
+        let enr = ENR.parse::<Enr>().unwrap();
+        for encoding in [
+            RecordEncoding::Enr,
+            RecordEncoding::Base64,
+            RecordEncoding::Hex,
+            RecordEncoding::Rlp,
+        ] {
+            let encoded = encode_record(&enr, encoding);
+            assert_eq!(RecordEncoding::detect(&encoded), encoding);
+            assert_eq!(decode_record(&encoded, None).unwrap(), enr);
+        }
+    }
+
+    #[test]
+    fn test_decode_standard_base64_and_prefixed_hex() {
+        let enr = ENR.parse::<Enr>().unwrap();
+        let standard = base64::engine::general_purpose::STANDARD.encode(alloy_rlp::encode(&enr));
+        assert_eq!(decode_record(standard.as_bytes(), None).unwrap(), enr);
+
+        let hex = format!("0x{}\n", hex::encode(alloy_rlp::encode(&enr)));
+        assert_eq!(decode_record(hex.as_bytes(), None).unwrap(), enr);
+    }
+}
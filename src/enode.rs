@@ -0,0 +1,261 @@
+//! Parsing and formatting of `enode://` URLs.
+//!
+//! An enode URL has the form `enode://<uncompressed-public-key>@<ip>:<tcp-port>?discport=<udp-port>`.
+//! IPv6 addresses are enclosed in brackets. When `discport` is absent the UDP port is the same as the
+//! TCP port and a port of `0` means the node does not listen on that protocol.
+use crate::enr_ext::CombinedKeyPublicExt;
+use crate::Enr;
+use enr::k256::ecdsa::VerifyingKey;
+use enr::{CombinedPublicKey, EnrPublicKey, NodeId};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use libp2p_identity::PeerId;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+const ENODE_PREFIX: &str = "enode://";
+
+/// The contents of an enode URL. This is a partial record: it has no signature or sequence number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enode {
+    /// The secp256k1 public key of the node.
+    pub public_key: VerifyingKey,
+    /// The IP address of the node, if known.
+    pub ip: Option<IpAddr>,
+    /// The TCP (RLPx) port.
+    pub tcp: Option<u16>,
+    /// The UDP (discovery) port.
+    pub udp: Option<u16>,
+}
+
+impl Enode {
+    /// Builds the enode of an ENR. IPv4 is preferred over IPv6. Returns `None` if the record does
+    /// not use a secp256k1 key, as enode URLs cannot express other key types.
+    pub fn from_enr(enr: &Enr) -> Option<Self> {
+        let public_key = match enr.public_key() {
+            CombinedPublicKey::Secp256k1(pk) => pk,
+            CombinedPublicKey::Ed25519(_) => return None,
+        };
+        let (ip, tcp, udp) = if let Some(ip) = enr.ip4() {
+            (Some(IpAddr::from(ip)), enr.tcp4(), enr.udp4())
+        } else if let Some(ip) = enr.ip6() {
+            // EIP-778: tcp6 and udp6 can be omitted when the ports are the same as tcp and udp.
+            (
+                Some(IpAddr::from(ip)),
+                enr.tcp6().or(enr.tcp4()),
+                enr.udp6().or(enr.udp4()),
+            )
+        } else {
+            (None, None, None)
+        };
+        Some(Enode {
+            public_key,
+            ip,
+            tcp,
+            udp,
+        })
+    }
+
+    /// The node id derived from the public key.
+    pub fn node_id(&self) -> NodeId {
+        NodeId::from(self.public_key)
+    }
+
+    /// The public key as a [`CombinedPublicKey`].
+    pub fn combined_public_key(&self) -> CombinedPublicKey {
+        CombinedPublicKey::from(self.public_key)
+    }
+
+    /// The libp2p `PeerId` derived from the public key.
+    pub fn peer_id(&self) -> PeerId {
+        self.combined_public_key().as_peer_id()
+    }
+
+    /// The 64 byte uncompressed public key, as used in the URL.
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        self.public_key.encode_uncompressed().to_vec()
+    }
+
+    /// Returns the `tcp` and `udp` multiaddrs of the node, if it has an IP.
+    pub fn multiaddr(&self) -> Vec<Multiaddr> {
+        let mut multiaddrs = Vec::new();
+        if let Some(ip) = self.ip {
+            if let Some(udp) = self.udp {
+                let mut multiaddr: Multiaddr = ip.into();
+                multiaddr.push(Protocol::Udp(udp));
+                multiaddrs.push(multiaddr);
+            }
+            if let Some(tcp) = self.tcp {
+                let mut multiaddr: Multiaddr = ip.into();
+                multiaddr.push(Protocol::Tcp(tcp));
+                multiaddrs.push(multiaddr);
+            }
+        }
+        multiaddrs
+    }
+}
+
+impl fmt::Display for Enode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            ENODE_PREFIX,
+            hex::encode(self.public_key_bytes())
+        )?;
+        let Some(ip) = self.ip else {
+            return Ok(());
+        };
+        match ip {
+            IpAddr::V4(ip) => write!(f, "@{}", ip)?,
+            IpAddr::V6(ip) => write!(f, "@[{}]", ip)?,
+        }
+        let tcp = self.tcp.unwrap_or(0);
+        let udp = self.udp.unwrap_or(0);
+        write!(f, ":{}", tcp)?;
+        if tcp != udp {
+            write!(f, "?discport={}", udp)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Enode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .trim()
+            .strip_prefix(ENODE_PREFIX)
+            .ok_or("Enode must start with enode://")?;
+        let (key_hex, address) = match s.split_once('@') {
+            Some((key_hex, address)) => (key_hex, Some(address)),
+            None => (s, None),
+        };
+
+        let key_bytes = hex::decode(key_hex).map_err(|_| "Invalid enode public key hex")?;
+        if key_bytes.len() != 64 {
+            return Err("Enode public key must be 64 bytes");
+        }
+        let mut sec1_bytes = vec![4u8];
+        sec1_bytes.extend(key_bytes);
+        let public_key =
+            VerifyingKey::from_sec1_bytes(&sec1_bytes).map_err(|_| "Invalid enode public key")?;
+
+        let Some(address) = address else {
+            return Ok(Enode {
+                public_key,
+                ip: None,
+                tcp: None,
+                udp: None,
+            });
+        };
+
+        let (host, query) = match address.split_once('?') {
+            Some((host, query)) => (host, Some(query)),
+            None => (address, None),
+        };
+
+        let (ip, port) = if let Some(bracketed) = host.strip_prefix('[') {
+            let (ip, rest) = bracketed
+                .split_once(']')
+                .ok_or("Missing closing bracket in IPv6 address")?;
+            let ip = ip.parse::<Ipv6Addr>().map_err(|_| "Invalid IPv6 address")?;
+            let port = match rest {
+                "" => None,
+                rest => Some(rest.strip_prefix(':').ok_or("Invalid enode port")?),
+            };
+            (IpAddr::V6(ip), port)
+        } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+            // An IPv6 address without brackets cannot carry a port.
+            (IpAddr::V6(ip), None)
+        } else {
+            let (ip, port) = match host.split_once(':') {
+                Some((ip, port)) => (ip, Some(port)),
+                None => (host, None),
+            };
+            (ip.parse::<IpAddr>().map_err(|_| "Invalid enode IP")?, port)
+        };
+
+        let tcp = port
+            .map(|port| port.parse::<u16>().map_err(|_| "Invalid enode port"))
+            .transpose()?;
+        let mut udp = tcp;
+        for (key, value) in query
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .filter_map(|pair| pair.split_once('='))
+        {
+            if key == "discport" {
+                udp = Some(value.parse::<u16>().map_err(|_| "Invalid enode discport")?);
+            }
+        }
+
+        Ok(Enode {
+            public_key,
+            ip: Some(ip),
+            tcp: tcp.filter(|port| *port != 0),
+            udp: udp.filter(|port| *port != 0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENR: &str = "enr:-Ku4QJsxkOibTc9FXfBWYmcdMAGwH4bnOOFb4BlTHfMdx_f0WN-u4IUqZcQVP9iuEyoxipFs7-Qd_rH_0HfyOQitc7IBh2F0dG5ldHOIAAAAAAAAAACEZXRoMpD1pf1CAAAAAP__________gmlkgnY0gmlwhLAJM9iJc2VjcDI1NmsxoQL2RyM26TKZzqnUsyycHQB4jnyg6Wi79rwLXtaZXty06YN1ZHCCW8w";
+
+    #[test]
+    fn test_enode_from_enr_round_trip() {
+        let enr = ENR.parse::<Enr>().unwrap();
+        let enode = Enode::from_enr(&enr).unwrap();
+        let url = enode.to_string();
+        assert!(url.ends_with("@176.9.51.216:0?discport=23500"));
+
+        let parsed = url.parse::<Enode>().unwrap();
+        assert_eq!(parsed, enode);
+        assert_eq!(parsed.node_id(), enr.node_id());
+        assert_eq!(parsed.peer_id(), crate::enr_ext::EnrExt::peer_id(&enr));
+    }
+
+    #[test]
+    fn test_enode_ipv6() {
+        let key = enr::CombinedKey::generate_secp256k1();
+        let enr = enr::Builder::default()
+            .ip6("2001:db8::1".parse().unwrap())
+            .tcp6(30303)
+            .udp6(30301)
+            .build(&key)
+            .unwrap();
+        let url = Enode::from_enr(&enr).unwrap().to_string();
+        assert!(url.ends_with("@[2001:db8::1]:30303?discport=30301"));
+
+        let enode = url.parse::<Enode>().unwrap();
+        assert_eq!(enode.ip, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(enode.tcp, Some(30303));
+        assert_eq!(enode.udp, Some(30301));
+    }
+
+    #[test]
+    fn test_enode_ipv6_shared_ports() {
+        let key = enr::CombinedKey::generate_secp256k1();
+        let enr = enr::Builder::default()
+            .ip6("2001:db8::1".parse().unwrap())
+            .tcp4(30303)
+            .udp4(30301)
+            .build(&key)
+            .unwrap();
+        let url = Enode::from_enr(&enr).unwrap().to_string();
+        assert!(url.ends_with("@[2001:db8::1]:30303?discport=30301"));
+    }
+
+    #[test]
+    fn test_enode_without_discport() {
+        let url = "enode://d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666@18.138.108.67:30303";
+        let enode = url.parse::<Enode>().unwrap();
+        assert_eq!(enode.tcp, Some(30303));
+        assert_eq!(enode.udp, Some(30303));
+        assert_eq!(enode.to_string(), url);
+    }
+}
@@ -1,10 +1,10 @@
 //! ENR extension trait to support libp2p integration.
 
+use crate::enode::Enode;
 use enr::{CombinedKey, CombinedPublicKey};
 use libp2p_core::{multiaddr::Protocol, Multiaddr};
 use libp2p_identity::{ed25519, secp256k1, KeyType, Keypair, PeerId, PublicKey};
 use std::convert::TryInto;
-use std::net::IpAddr;
 
 pub type Enr = enr::Enr<CombinedKey>;
 
@@ -42,8 +42,8 @@ pub trait EnrExt {
     /// Returns the quic6 port if one is set.
     fn quic6(&self) -> Option<u16>;
 
-    /// Returns the ENODE address of the ENR.
-    fn enode_id(&self) -> String;
+    /// Returns the ENODE address of the ENR, if it uses a secp256k1 key.
+    fn enode_id(&self) -> Option<String>;
 }
 
 /// Extend ENR CombinedPublicKey for libp2p types.
@@ -60,6 +60,9 @@ pub trait CombinedKeyExt {
 
     /// Converts a [`secp256k1::Keypair`] into and Enr [`CombinedKey`].
     fn from_secp256k1(key: &secp256k1::Keypair) -> CombinedKey;
+
+    /// Converts an ENR combined key into a libp2p key.
+    fn to_libp2p(&self) -> Keypair;
 }
 
 impl EnrExt for Enr {
@@ -259,40 +262,10 @@ impl EnrExt for Enr {
         multiaddrs
     }
 
-    /// Returns the ENODE ID of the ENR as a string to be printed
-    fn enode_id(&self) -> String {
-        let node_id = self.node_id();
-        let enode = format!("enode://{}", hex::encode(node_id.raw()));
-        // Preference ipv4 over ipv6
-        if let Some((ip, tcp_port, udp_port)) = {
-            if let Some(ip) = self.ip4() {
-                let tcp_port = self.tcp4();
-                let udp_port = self.udp4();
-                Some((IpAddr::from(ip), tcp_port, udp_port))
-            } else if let Some(ip) = self.ip6() {
-                let tcp_port = self.tcp4();
-                let udp_port = self.udp4();
-                Some((IpAddr::from(ip), tcp_port, udp_port))
-            } else {
-                None
-            }
-        } {
-            match (tcp_port, udp_port) {
-                (Some(tcp_port), Some(udp_port)) => {
-                    if tcp_port != udp_port {
-                        format!("{}@{}:{}?discport={}", enode, ip, tcp_port, udp_port)
-                    } else {
-                        format!("{}@{}:{}", enode, ip, tcp_port)
-                    }
-                }
-                (Some(tcp_port), None) => format!("{}@{}:{}", enode, ip, tcp_port),
-                (None, Some(udp_port)) => format!("{}@{}?discport={}", enode, ip, udp_port),
-                (None, None) => format!("{}@{}", enode, ip),
-            }
-        } else {
-            // There was no IP address, so leave blank and just print the hex address
-            enode
-        }
+    /// Returns the ENODE ID of the ENR as a string to be printed. Enode URLs only support
+    /// secp256k1 keys, so this is `None` for other key types.
+    fn enode_id(&self) -> Option<String> {
+        Enode::from_enr(self).map(|enode| enode.to_string())
     }
 }
 
@@ -346,50 +319,46 @@ impl CombinedKeyExt for CombinedKey {
             .expect("libp2p key must be valid");
         CombinedKey::Secp256k1(secret)
     }
+
+    fn to_libp2p(&self) -> Keypair {
+        match self {
+            CombinedKey::Secp256k1(key) => {
+                let mut bytes: [u8; 32] = key.to_bytes().into();
+                let secret = secp256k1::SecretKey::try_from_bytes(&mut bytes)
+                    .expect("enr key must be valid");
+                secp256k1::Keypair::from(secret).into()
+            }
+            CombinedKey::Ed25519(key) => {
+                Keypair::ed25519_from_bytes(key.to_bytes()).expect("enr key must be valid")
+            }
+        }
+    }
 }
 
-// helper function to convert a peer_id to a node_id. This is only possible for secp256k1/ed25519 libp2p
-// peer_ids
-#[cfg(test)]
-pub fn peer_id_to_node_id(peer_id: &PeerId) -> Result<enr::NodeId, String> {
-    use tiny_keccak::{Hasher, Keccak};
-    // A libp2p peer id byte representation should be 2 length bytes + 4 protobuf bytes + compressed pk bytes
-    // if generated from a PublicKey with Identity multihash.
-    let pk_bytes = &peer_id.to_bytes()[2..];
-
-    let public_key = PublicKey::try_decode_protobuf(pk_bytes).map_err(|e| {
-        format!(
-            " Cannot parse libp2p public key public key from peer id: {}",
-            e
-        )
-    })?;
+/// Extracts the public key embedded in a libp2p `PeerId`. This is only possible for secp256k1/ed25519
+/// peer ids, which embed the key with the identity multihash.
+pub fn peer_id_to_public_key(peer_id: &PeerId) -> Result<CombinedPublicKey, String> {
+    let multihash: &libp2p_core::multihash::Multihash<64> = peer_id.as_ref();
+    // The identity multihash code.
+    if multihash.code() != 0 {
+        return Err(format!("Peer id {} does not embed a public key", peer_id));
+    }
+    let public_key = PublicKey::try_decode_protobuf(multihash.digest())
+        .map_err(|e| format!("Cannot parse libp2p public key from peer id: {}", e))?;
 
     match public_key.key_type() {
         KeyType::Secp256k1 => {
-            let pk = public_key
-                .clone()
-                .try_into_secp256k1()
-                .expect("right key type");
-            let uncompressed_key_bytes = &pk.to_bytes_uncompressed()[1..];
-            let mut output = [0_u8; 32];
-            let mut hasher = Keccak::v256();
-            hasher.update(uncompressed_key_bytes);
-            hasher.finalize(&mut output);
-            Ok(enr::NodeId::parse(&output).expect("Must be correct length"))
+            let pk = public_key.try_into_secp256k1().expect("right key type");
+            let pk = enr::k256::ecdsa::VerifyingKey::from_sec1_bytes(&pk.to_bytes())
+                .map_err(|_| format!("Invalid secp256k1 public key in peer id {}", peer_id))?;
+            Ok(CombinedPublicKey::from(pk))
         }
         KeyType::Ed25519 => {
-            let pk = public_key
-                .clone()
-                .try_into_ed25519()
-                .expect("right key type");
-            let uncompressed_key_bytes = pk.to_bytes();
-            let mut output = [0_u8; 32];
-            let mut hasher = Keccak::v256();
-            hasher.update(&uncompressed_key_bytes);
-            hasher.finalize(&mut output);
-            Ok(enr::NodeId::parse(&output).expect("Must be correct length"))
+            let pk = public_key.try_into_ed25519().expect("right key type");
+            let pk = enr::ed25519_dalek::VerifyingKey::from_bytes(&pk.to_bytes())
+                .map_err(|_| format!("Invalid ed25519 public key in peer id {}", peer_id))?;
+            Ok(CombinedPublicKey::from(pk))
         }
-
         _ => Err(format!("Unsupported public key from peer {}", peer_id)),
     }
 }
@@ -411,8 +380,12 @@ mod tests {
         let peer_id = libp2p_kp.public().to_peer_id();
 
         let enr = enr::Builder::default().build(&secret_key).unwrap();
-        let node_id = peer_id_to_node_id(&peer_id).unwrap();
+        let node_id = enr::NodeId::from(peer_id_to_public_key(&peer_id).unwrap());
 
+        assert_eq!(
+            hex::encode(node_id.raw()),
+            "f91b22730519e45b101948da714708d32751a119767447ea456b97d70833f2f9"
+        );
         assert_eq!(enr.node_id(), node_id);
     }
 
@@ -429,8 +402,12 @@ mod tests {
         let peer_id = libp2p_kp.public().to_peer_id();
 
         let enr = enr::Builder::default().build(&secret_key).unwrap();
-        let node_id = peer_id_to_node_id(&peer_id).unwrap();
+        let node_id = enr::NodeId::from(peer_id_to_public_key(&peer_id).unwrap());
 
+        assert_eq!(
+            hex::encode(node_id.raw()),
+            "4fccbcd9924aa004dd2e54a4b890a5e41c42956e1cdfe7eacbd0eab81e4bfdd7"
+        );
         assert_eq!(enr.node_id(), node_id);
     }
 }
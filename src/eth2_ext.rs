@@ -7,6 +7,8 @@ use ssz_derive::{Decode, Encode};
 pub const ETH2_ENR_KEY: &str = "eth2";
 /// The ENR field specifying the subnet bitfield.
 pub const BITFIELD_ENR_KEY: &str = "attnets";
+/// The ENR field specifying the sync committee subnet bitfield.
+pub const SYNC_COMMITTEE_BITFIELD_ENR_KEY: &str = "syncnets";
 
 #[derive(Debug, Clone, PartialEq, Default, Encode, Decode, Eq)]
 pub struct EnrForkId {
@@ -0,0 +1,108 @@
+//! Custom ENR fields given as `name=type:value`.
+use alloy_rlp::Encodable;
+use bytes::{Bytes, BytesMut};
+use std::str::FromStr;
+
+/// Keys that define the identity of a record, derived from the signing key by the ENR builder.
+/// Custom fields only set them with `--force`, `update` and `rotate` never copy or change them.
+pub const RESERVED_KEYS: [&str; 3] = ["id", "secp256k1", "ed25519"];
+
+/// The supported value types, in the form accepted on the command line.
+pub const FIELD_TYPES: [&str; 7] = [
+    "u64", "u16", "bytes", "string", "ssz-hex", "rlp-hex", "bool",
+];
+
+/// A custom key with its RLP encoded value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub key: String,
+    pub rlp: Bytes,
+}
+
+impl Field {
+    /// Whether the field overrides a key derived from the signing key.
+    pub fn is_reserved(&self) -> bool {
+        RESERVED_KEYS.contains(&self.key.as_str())
+    }
+
+    /// Encodes a value of the given type.
+    pub fn new(key: &str, field_type: &str, value: &str) -> Result<Self, &'static str> {
+        if key.is_empty() {
+            return Err("Field name cannot be empty");
+        }
+        let hex_bytes = |value: &str| {
+            hex::decode(value.trim_start_matches("0x"))
+                .map(Bytes::from)
+                .map_err(|_| "Invalid hex value")
+        };
+        let rlp = match field_type {
+            "u64" => encode(&value.parse::<u64>().map_err(|_| "Invalid u64 value")?),
+            "u16" => encode(&value.parse::<u16>().map_err(|_| "Invalid u16 value")?),
+            // SSZ values are stored in the record as an RLP byte string.
+            "bytes" | "ssz-hex" => encode(&hex_bytes(value)?),
+            "string" => encode(&value.as_bytes()),
+            "bool" => encode(&value.parse::<bool>().map_err(|_| "Invalid bool value")?),
+            "rlp-hex" => {
+                let rlp = hex_bytes(value)?;
+                // The value must be exactly one RLP item.
+                let mut buf = rlp.as_ref();
+                let header =
+                    alloy_rlp::Header::decode(&mut buf).map_err(|_| "Invalid RLP value")?;
+                if header.payload_length != buf.len() {
+                    return Err("RLP value must be a single item");
+                }
+                rlp
+            }
+            _ => return Err("Unknown field type"),
+        };
+        Ok(Field {
+            key: key.to_string(),
+            rlp,
+        })
+    }
+}
+
+fn encode<T: Encodable + ?Sized>(value: &T) -> Bytes {
+    let mut out = BytesMut::with_capacity(value.length());
+    value.encode(&mut out);
+    out.freeze()
+}
+
+impl FromStr for Field {
+    type Err = &'static str;
+
+    /// Parses `name=type:value`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, typed_value) = s.split_once('=').ok_or("Fields take name=type:value")?;
+        let (field_type, value) = typed_value
+            .split_once(':')
+            .ok_or("Fields take name=type:value")?;
+        Field::new(key, field_type, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_encodings() {
+        let cases = [
+            ("a=u64:1024", "820400"),
+            ("a=u16:0", "80"),
+            ("a=bytes:0x0102", "820102"),
+            ("a=string:hi", "826869"),
+            ("a=ssz-hex:00", "00"),
+            ("a=rlp-hex:c20102", "c20102"),
+            ("a=bool:true", "01"),
+            ("a=bool:false", "80"),
+        ];
+        for (input, expected) in cases {
+            let field = input.parse::<Field>().unwrap();
+            assert_eq!(hex::encode(&field.rlp), expected, "{}", input);
+        }
+        assert!("a=rlp-hex:c2010203".parse::<Field>().is_err());
+        assert!("a=u16:65536".parse::<Field>().is_err());
+        assert!("secp256k1=bytes:00".parse::<Field>().unwrap().is_reserved());
+    }
+}
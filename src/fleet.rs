@@ -0,0 +1,276 @@
+//! Rebuilds the records of a fleet of nodes from a shared spec, each signed with its own key.
+use crate::key_format::ClientFormat;
+use crate::keys::{self, KeyType};
+use crate::seq::{self, SeqPolicy};
+use crate::spec::EnrSpec;
+use crate::update;
+use crate::Enr;
+use enr::{CombinedKey, EnrKey, EnrPublicKey};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+/// The extension of the record files written for each key.
+const ENR_EXTENSION: &str = "enr";
+
+/// The addresses of a node given by the IP map.
+type NodeIps = (Option<Ipv4Addr>, Option<Ipv6Addr>);
+
+/// Reads a CSV of `name,ip[,ip]` lines mapping key file names (without extension) to the IPv4
+/// and IPv6 address of the node. Empty lines, `#` comments and a `name,...` header are skipped.
+fn read_ip_map(path: &Path) -> Result<BTreeMap<String, NodeIps>, String> {
+    let csv = fs::read_to_string(path).map_err(|_| "Cannot read the IP map")?;
+    let mut ip_map = BTreeMap::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (number == 0 && line.starts_with("name,")) {
+            continue;
+        }
+        let mut columns = line.split(',').map(str::trim);
+        let name = columns.next().expect("split yields a column").to_string();
+        let (mut ip4, mut ip6) = (None, None);
+        for ip in columns {
+            let ip = ip
+                .parse::<IpAddr>()
+                .map_err(|_| format!("Invalid IP address on line {} of the IP map", number + 1))?;
+            let (duplicate, family) = match ip {
+                IpAddr::V4(ip) => (ip4.replace(ip).is_some(), "IPv4"),
+                IpAddr::V6(ip) => (ip6.replace(ip).is_some(), "IPv6"),
+            };
+            if duplicate {
+                return Err(format!(
+                    "Line {} of the IP map has more than one {} address",
+                    number + 1,
+                    family
+                ));
+            }
+        }
+        if ip_map.insert(name.clone(), (ip4, ip6)).is_some() {
+            return Err(format!("{} is listed twice in the IP map", name));
+        }
+    }
+    Ok(ip_map)
+}
+
+/// The key files in `dir` with the given extension, by name. Hidden files and any other files,
+/// such as the records or an IP map kept next to the keys, are skipped.
+fn key_files(dir: &Path, extension: &str) -> Result<Vec<(String, PathBuf)>, &'static str> {
+    let mut key_files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|_| "Cannot read the keys directory")? {
+        let path = entry.map_err(|_| "Cannot read the keys directory")?.path();
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let is_key = path.extension().is_some_and(|ext| ext == extension);
+        if path.is_file() && !name.starts_with('.') && is_key {
+            key_files.push((name.to_string(), path.clone()));
+        }
+    }
+    key_files.sort();
+    Ok(key_files)
+}
+
+/// Builds the record for `key` from `spec`. With a previous record of the same key, `None` is
+/// returned if the content would not change, otherwise the sequence number follows the spec's
+/// `seq_policy`, incrementing the previous one by default.
+pub fn rebuild_record(
+    spec: &EnrSpec,
+    key: &CombinedKey,
+    previous: Option<&Enr>,
+) -> Result<Option<Enr>, String> {
+    let mut spec = spec.clone();
+    let explicit = spec.seq;
+    if let Some(previous) = previous {
+        spec.seq = Some(previous.seq());
+        if spec.build(key, false)?.compare_content(previous) {
+            return Ok(None);
+        }
+    }
+    spec.seq = seq::next_seq(
+        spec.seq_policy,
+        None,
+        previous.map(Enr::seq),
+        explicit,
+        Some(SeqPolicy::Increment),
+    )?;
+    spec.build(key, false).map(Some)
+}
+
+pub fn rebuild(matches: &clap::ArgMatches) -> Result<(), String> {
+    let keys_dir = Path::new(
+        matches
+            .get_one::<String>("keys-dir")
+            .expect("Must supply a keys directory"),
+    );
+    let template = EnrSpec::from_file(Path::new(
+        matches
+            .get_one::<String>("template")
+            .expect("Must supply a template"),
+    ))?;
+    if template.seq_state.is_some() {
+        return Err(
+            "The template cannot set seq_state, each node continues from its own record".into(),
+        );
+    }
+    let ip_map = matches
+        .get_one::<String>("ip-map")
+        .map(|path| read_ip_map(Path::new(path)))
+        .transpose()?
+        .unwrap_or_default();
+    let enr_dir = matches
+        .get_one::<String>("enr-dir")
+        .map_or(keys_dir, Path::new);
+    let format = matches
+        .get_one::<String>("format")
+        .map(|format| format.parse::<ClientFormat>())
+        .transpose()?;
+    let key_type = KeyType::from_matches(matches)?.or(template.key_type);
+    let dry_run = matches.get_flag("dry-run");
+
+    let key_files = key_files(
+        keys_dir,
+        matches
+            .get_one::<String>("key-extension")
+            .expect("has a default"),
+    )?;
+    if key_files.is_empty() {
+        return Err("No key files found".into());
+    }
+    if let Some(name) = ip_map
+        .keys()
+        .find(|name| !key_files.iter().any(|(key_name, _)| key_name == *name))
+    {
+        return Err(format!("{} in the IP map has no key file", name));
+    }
+    for (name, key_file) in key_files {
+        let key = match format {
+            Some(format) => format
+                .decode(&fs::read(&key_file).map_err(|_| "Cannot read key file")?)
+                .map_err(|e| format!("{}: {}", name, e))?,
+            None => {
+                keys::read_key_file(&key_file, key_type).map_err(|e| format!("{}: {}", name, e))?
+            }
+        };
+
+        let mut spec = template.clone();
+        if let Some((ip4, ip6)) = ip_map.get(&name) {
+            spec.ip = ip4.or(spec.ip);
+            spec.ip6 = ip6.or(spec.ip6);
+        }
+
+        let enr_file = enr_dir.join(format!("{}.{}", name, ENR_EXTENSION));
+        let previous = match fs::read_to_string(&enr_file) {
+            Ok(previous) => {
+                let previous = previous
+                    .trim()
+                    .parse::<Enr>()
+                    .map_err(|_| format!("{}: invalid ENR in {}", name, enr_file.display()))?;
+                if previous.public_key().encode() == key.public().encode() {
+                    Some(previous)
+                } else {
+                    println!(
+                        "Warning: {} belongs to a different key, starting a new record",
+                        enr_file.display()
+                    );
+                    None
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("Cannot read {}: {}", enr_file.display(), e)),
+        };
+
+        println!("{}:", name);
+        match rebuild_record(&spec, &key, previous.as_ref())? {
+            None => println!("unchanged"),
+            Some(enr) => {
+                match &previous {
+                    Some(previous) => update::print_diff(previous, &enr),
+                    None => println!("new record, seq: {}", enr.seq()),
+                }
+                if !dry_run {
+                    fs::write(&enr_file, enr.to_base64()).map_err(|_| "Cannot write ENR file")?;
+                    println!("{}", enr.to_base64());
+                }
+            }
+        }
+        println!();
+    }
+    if dry_run {
+        println!("Dry run, no records were written");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebuild_bumps_seq_only_on_change() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut spec = EnrSpec {
+            udp_port: Some(9000),
+            ..Default::default()
+        };
+        let first = rebuild_record(&spec, &key, None).unwrap().unwrap();
+        assert!(rebuild_record(&spec, &key, Some(&first)).unwrap().is_none());
+
+        spec.udp_port = Some(9001);
+        let second = rebuild_record(&spec, &key, Some(&first)).unwrap().unwrap();
+        assert_eq!(second.seq(), first.seq() + 1);
+        assert_eq!(second.udp4(), Some(9001));
+        assert!(second.verify());
+
+        spec.seq_policy = Some(SeqPolicy::Timestamp);
+        spec.udp_port = Some(9002);
+        let third = rebuild_record(&spec, &key, Some(&second)).unwrap().unwrap();
+        assert!(third.seq() > 1_600_000_000);
+        assert!(rebuild_record(&spec, &key, Some(&third)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_key_files_skip_other_files() {
+        let dir = std::env::temp_dir().join(format!("enr-cli-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in [
+            "node-0.key",
+            "node-1.key",
+            "node-0.enr",
+            "ips.csv",
+            "README",
+            ".hidden.key",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let names: Vec<_> = key_files(&dir, "key")
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["node-0", "node-1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ip_map_rows() {
+        let path = std::env::temp_dir().join(format!("enr-cli-ips-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "name,ip,ip6\nnode-0,10.0.0.1,::1\n# comment\nnode-1,10.0.0.2\n",
+        )
+        .unwrap();
+        let ip_map = read_ip_map(&path).unwrap();
+        assert_eq!(
+            ip_map["node-0"],
+            (Some(Ipv4Addr::new(10, 0, 0, 1)), Some(Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(ip_map["node-1"], (Some(Ipv4Addr::new(10, 0, 0, 2)), None));
+
+        fs::write(&path, "node-0,10.0.0.1,10.0.0.2\n").unwrap();
+        assert!(read_ip_map(&path).is_err());
+        fs::write(&path, "node-0,10.0.0.1\nnode-0,10.0.0.2\n").unwrap();
+        assert!(read_ip_map(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! Searches for node keys whose node id has a given prefix or lies at a given distance from a
+//! target, for testing Kademlia routing.
+use crate::key_format;
+use crate::keys::KeyType;
+use enr::{CombinedKey, EnrKey, NodeId};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the search progress is printed.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Searches expected to take more keys than this are refused, they would run for days.
+const MAX_EXPECTED_ATTEMPTS: f64 = (1u64 << 40) as f64;
+
+/// The node ids searched for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Node ids starting with these lowercase hex digits.
+    Prefix(String),
+    /// Node ids at this log2 distance (1 to 256) from a node id, as used by discv5 `FINDNODE`.
+    Near { node_id: NodeId, distance: u32 },
+}
+
+impl Target {
+    /// Reads `--prefix`, or `--near` and `--distance`.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Self, &'static str> {
+        if let Some(prefix) = matches.get_one::<String>("prefix") {
+            let prefix = prefix.trim_start_matches("0x").to_ascii_lowercase();
+            if prefix.is_empty()
+                || prefix.len() > 64
+                || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err("The prefix must be 1 to 64 hex digits");
+            }
+            return Ok(Target::Prefix(prefix));
+        }
+        let node_id = hex::decode(
+            matches
+                .get_one::<String>("near")
+                .expect("Must supply a prefix or a node id")
+                .trim_start_matches("0x"),
+        )
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or("The node id must be 32 hex bytes")?;
+        let distance = matches
+            .get_one::<String>("distance")
+            .expect("Must supply a distance")
+            .parse::<u32>()
+            .ok()
+            .filter(|distance| (1..=256).contains(distance))
+            .ok_or("The distance must be between 1 and 256")?;
+        Ok(Target::Near {
+            node_id: NodeId::new(&node_id),
+            distance,
+        })
+    }
+
+    pub fn matches(&self, node_id: &NodeId) -> bool {
+        match self {
+            Target::Prefix(prefix) => hex::encode(node_id.raw()).starts_with(prefix.as_str()),
+            Target::Near {
+                node_id: target,
+                distance,
+            } => log2_distance(target, node_id) == *distance,
+        }
+    }
+
+    /// The average number of keys tried before a match.
+    pub fn expected_attempts(&self) -> f64 {
+        match self {
+            Target::Prefix(prefix) => 16f64.powi(prefix.len() as i32),
+            // Half of the ids at distance d or less are at distance d.
+            Target::Near { distance, .. } => 2f64.powi(257 - *distance as i32),
+        }
+    }
+}
+
+/// The log2 distance between two node ids: the bit length of their XOR, 0 for equal ids.
+pub fn log2_distance(a: &NodeId, b: &NodeId) -> u32 {
+    a.raw()
+        .iter()
+        .zip(b.raw().iter())
+        .enumerate()
+        .find(|(_, (a, b))| a != b)
+        .map_or(0, |(i, (a, b))| {
+            (32 - i as u32) * 8 - (a ^ b).leading_zeros()
+        })
+}
+
+/// Generates keys on `threads` threads until one matches `target`. `progress` is called with the
+/// number of keys tried and the elapsed time about once a second.
+pub fn grind(
+    target: &Target,
+    key_type: KeyType,
+    threads: usize,
+    mut progress: impl FnMut(u64, Duration),
+) -> CombinedKey {
+    let attempts = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    let found = Mutex::new(None);
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let key = key_type.generate();
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    if target.matches(&NodeId::from(key.public())) {
+                        found.lock().expect("not poisoned").get_or_insert(key);
+                        done.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+        let mut last_progress = start;
+        while !done.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(50));
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                last_progress = Instant::now();
+                progress(attempts.load(Ordering::Relaxed), start.elapsed());
+            }
+        }
+    });
+    found
+        .into_inner()
+        .expect("not poisoned")
+        .expect("a key was found")
+}
+
+/// Formats a duration in seconds as the largest fitting unit.
+fn human_duration(seconds: f64) -> String {
+    match seconds {
+        s if s < 120.0 => format!("{:.0}s", s),
+        s if s < 7200.0 => format!("{:.0}m", s / 60.0),
+        s if s < 172_800.0 => format!("{:.0}h", s / 3600.0),
+        s => format!("{:.0}d", s / 86400.0),
+    }
+}
+
+pub fn grind_key(matches: &clap::ArgMatches) -> Result<(), String> {
+    if !matches.contains_id("out")
+        && !matches.get_flag("encrypt")
+        && !matches.get_flag("print-secret")
+    {
+        return Err("Use --out to save the key or --print-secret to print it".into());
+    }
+    let target = Target::from_matches(matches)?;
+    let expected = target.expected_attempts();
+    if expected > MAX_EXPECTED_ATTEMPTS {
+        return Err(format!(
+            "The target is expected to take {:.0} keys, more than the limit of 2^40, use a shorter prefix or a larger distance",
+            expected
+        ));
+    }
+    let key_type = KeyType::from_matches(matches)?.unwrap_or(KeyType::Secp256k1);
+    let threads = match matches.get_one::<String>("threads") {
+        Some(threads) => threads
+            .parse::<usize>()
+            .ok()
+            .filter(|threads| *threads > 0)
+            .ok_or("Invalid thread count")?,
+        None => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+    };
+
+    eprintln!(
+        "Searching with {} threads, {:.0} keys expected",
+        threads, expected
+    );
+    let key = grind(&target, key_type, threads, |attempts, elapsed| {
+        let rate = attempts as f64 / elapsed.as_secs_f64();
+        // The search is memoryless, so the expected remaining time never shrinks with progress.
+        let eta = expected / rate;
+        eprint!(
+            "\r{} keys tried, {:.0} keys/s, ETA {}   ",
+            attempts,
+            rate,
+            human_duration(eta)
+        );
+        let _ = std::io::stderr().flush();
+    });
+    eprintln!();
+    key_format::save_key(&key, matches).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grind_finds_matching_keys() {
+        let target = NodeId::random();
+        for distance in [256, 254] {
+            let near = Target::Near {
+                node_id: target,
+                distance,
+            };
+            let key = grind(&near, KeyType::Secp256k1, 2, |_, _| {});
+            assert_eq!(
+                log2_distance(&target, &NodeId::from(key.public())),
+                distance
+            );
+        }
+        assert_eq!(log2_distance(&target, &target), 0);
+
+        // 10 hex digits is the longest prefix allowed, distance 217 the shortest distance.
+        assert!(Target::Prefix("a".repeat(10)).expected_attempts() <= MAX_EXPECTED_ATTEMPTS);
+        assert!(Target::Prefix("a".repeat(11)).expected_attempts() > MAX_EXPECTED_ATTEMPTS);
+        let near = |distance| Target::Near {
+            node_id: target,
+            distance,
+        };
+        assert!(near(217).expected_attempts() <= MAX_EXPECTED_ATTEMPTS);
+        assert!(near(216).expected_attempts() > MAX_EXPECTED_ATTEMPTS);
+
+        let prefix = Target::Prefix("ab".into());
+        let key = grind(&prefix, KeyType::Ed25519, 2, |_, _| {});
+        assert!(hex::encode(NodeId::from(key.public()).raw()).starts_with("ab"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Inspects the network directory of a client: decodes the stored key and record and checks that
+//! they belong to the same node.
+use crate::encoding;
+use crate::enr_ext::{CombinedKeyPublicExt, EnrExt};
+use crate::key_format::ClientFormat;
+use crate::keys::KeyType;
+use crate::keystore::{self, Keystore};
+use crate::Enr;
+use enr::{CombinedKey, EnrKey, NodeId};
+use std::path::{Path, PathBuf};
+
+/// Where a client keeps its node key and record, relative to the directory given.
+struct Layout {
+    client: &'static str,
+    format: ClientFormat,
+    key_files: &'static [&'static str],
+    enr_files: &'static [&'static str],
+}
+
+const LAYOUTS: [Layout; 5] = [
+    Layout {
+        client: "Lighthouse",
+        format: ClientFormat::Lighthouse,
+        key_files: &["key.dat", "beacon/network/key.dat", "network/key.dat"],
+        enr_files: &["enr.dat", "beacon/network/enr.dat", "network/enr.dat"],
+    },
+    Layout {
+        client: "Prysm",
+        format: ClientFormat::Prysm,
+        key_files: &["network-keys", "beaconchaindata/network-keys"],
+        enr_files: &[],
+    },
+    Layout {
+        client: "Teku",
+        format: ClientFormat::Teku,
+        key_files: &[
+            "generated-node-key.dat",
+            "kvstore/generated-node-key.dat",
+            "beacon/kvstore/generated-node-key.dat",
+        ],
+        enr_files: &[],
+    },
+    Layout {
+        client: "Nimbus",
+        format: ClientFormat::Nimbus,
+        key_files: &["netkey"],
+        enr_files: &[],
+    },
+    Layout {
+        client: "Lodestar",
+        format: ClientFormat::Lodestar,
+        key_files: &["peer-id.json", "beacon/peer-id.json"],
+        enr_files: &["enr", "beacon/enr"],
+    },
+];
+
+fn find(dir: &Path, candidates: &[&str]) -> Option<PathBuf> {
+    candidates
+        .iter()
+        .map(|candidate| dir.join(candidate))
+        .find(|path| path.is_file())
+}
+
+fn read_key(
+    layout: &Layout,
+    path: &Path,
+    password_file: Option<&String>,
+) -> Result<CombinedKey, &'static str> {
+    let bytes = std::fs::read(path).map_err(|_| "Cannot read key file")?;
+    match layout.format {
+        ClientFormat::Nimbus => {
+            let password_file = password_file
+                .ok_or("The netkey is encrypted, pass --password-file to decrypt it")?;
+            let password = keystore::read_password_file(Path::new(password_file))?;
+            Keystore::from_json(&bytes)?.decrypt(&password, None)
+        }
+        format => format.decode(&bytes),
+    }
+}
+
+fn read_enr(path: &Path) -> Result<Enr, &'static str> {
+    let bytes = std::fs::read(path).map_err(|_| "Cannot read ENR file")?;
+    encoding::decode_record(&bytes, None)
+}
+
+/// Prints what a client has stored in the directory, returning the problems found.
+fn inspect_layout(
+    dir: &Path,
+    layout: &Layout,
+    password_file: Option<&String>,
+) -> Option<Vec<String>> {
+    let key_path = find(dir, layout.key_files);
+    let enr_path = find(dir, layout.enr_files);
+    if key_path.is_none() && enr_path.is_none() {
+        return None;
+    }
+
+    println!("{} layout detected", layout.client);
+    let mut problems = Vec::new();
+
+    let key = key_path.and_then(|path| {
+        println!("Key file: {}", path.display());
+        match read_key(layout, &path, password_file) {
+            Ok(key) => {
+                println!("  Key Type: {}", KeyType::of(&key.public()));
+                println!(
+                    "  NodeId: {}",
+                    hex::encode(NodeId::from(key.public()).raw())
+                );
+                println!("  Libp2p PeerId: {}", key.public().as_peer_id());
+                Some(key)
+            }
+            Err(e) => {
+                problems.push(format!("Cannot decode {}: {}", path.display(), e));
+                None
+            }
+        }
+    });
+
+    let enr = enr_path.and_then(|path| {
+        println!("ENR file: {}", path.display());
+        match read_enr(&path) {
+            Ok(enr) => {
+                println!("  ENR: {}", enr.to_base64());
+                println!("  Sequence No: {}", enr.seq());
+                println!("  NodeId: {}", hex::encode(enr.node_id().raw()));
+                println!("  Libp2p PeerId: {}", enr.peer_id());
+                for multiaddr in enr.multiaddr() {
+                    println!("  {}", multiaddr);
+                }
+                if !enr.verify() {
+                    problems.push(format!("{} has an invalid signature", path.display()));
+                }
+                Some(enr)
+            }
+            Err(e) => {
+                problems.push(format!("Cannot decode {}: {}", path.display(), e));
+                None
+            }
+        }
+    });
+
+    if let (Some(key), Some(enr)) = (&key, &enr) {
+        let key_node_id = NodeId::from(key.public());
+        if key_node_id != enr.node_id() {
+            problems.push(format!(
+                "The record belongs to node {} but the key is for node {}. The ENR file is likely outdated after a key rotation",
+                hex::encode(enr.node_id().raw()),
+                hex::encode(key_node_id.raw())
+            ));
+        }
+    }
+    Some(problems)
+}
+
+pub fn inspect_dir(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let dir = Path::new(
+        matches
+            .get_one::<String>("dir")
+            .expect("Must supply a directory"),
+    );
+    if !dir.is_dir() {
+        return Err("Not a directory");
+    }
+    let client = matches
+        .get_one::<String>("client")
+        .map(|client| client.parse::<ClientFormat>())
+        .transpose()?;
+    let password_file = matches.get_one::<String>("password-file");
+
+    let mut detected = false;
+    for layout in LAYOUTS
+        .iter()
+        .filter(|layout| client.is_none_or(|client| client == layout.format))
+    {
+        let Some(problems) = inspect_layout(dir, layout, password_file) else {
+            continue;
+        };
+        if detected {
+            println!();
+        }
+        detected = true;
+        if problems.is_empty() {
+            println!("OK: the stored key and record are consistent");
+        }
+        for problem in problems {
+            println!("MISMATCH: {}", problem);
+        }
+    }
+    if !detected {
+        return Err("No known client key or record files found");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_outdated_lighthouse_record() {
+        let dir = std::env::temp_dir().join(format!("enr-cli-inspect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = CombinedKey::generate_secp256k1();
+        let old_enr = enr::Builder::default()
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        std::fs::write(dir.join("key.dat"), key.encode()).unwrap();
+        std::fs::write(dir.join("enr.dat"), old_enr.to_base64()).unwrap();
+
+        let problems = inspect_layout(&dir, &LAYOUTS[0], None).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("outdated"));
+
+        let enr = enr::Builder::default().build(&key).unwrap();
+        std::fs::write(dir.join("enr.dat"), enr.to_base64()).unwrap();
+        assert!(inspect_layout(&dir, &LAYOUTS[0], None).unwrap().is_empty());
+        assert!(inspect_layout(&dir, &LAYOUTS[1], None).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,540 @@
+//! Reads and writes node keys in the on-disk formats of the different clients and in standard key
+//! encodings.
+use crate::derive;
+use crate::enr_ext::{CombinedKeyExt, CombinedKeyPublicExt};
+use crate::keys::{self, KeyType};
+use crate::keystore::{self, KdfParams, Keystore};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use enr::{CombinedKey, EnrKey, NodeId};
+use k256::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use libp2p_identity::Keypair;
+use serde_json::json;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// The clients whose node key files can be read and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientFormat {
+    /// Lighthouse `key.dat`: the raw secp256k1 secret.
+    Lighthouse,
+    /// Prysm `network-keys`: the hex encoded secp256k1 secret. Older versions stored a hex
+    /// encoded protobuf libp2p key, which is also read.
+    Prysm,
+    /// Teku `generated-node-key.dat`: a `0x`-prefixed hex encoded protobuf libp2p key.
+    Teku,
+    /// Nimbus `netkey`: an encrypted keystore holding a protobuf libp2p key. Handled by
+    /// [`Keystore`] as it needs a password.
+    Nimbus,
+    /// Lodestar `peer-id.json`: the base64 protobuf libp2p keys alongside the peer id.
+    Lodestar,
+    /// Geth `nodekey`: the hex encoded secp256k1 secret.
+    Geth,
+    /// Reth `discovery-secret`: the hex encoded secp256k1 secret.
+    Reth,
+}
+
+impl FromStr for ClientFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lighthouse" => Ok(ClientFormat::Lighthouse),
+            "prysm" => Ok(ClientFormat::Prysm),
+            "teku" => Ok(ClientFormat::Teku),
+            "nimbus" => Ok(ClientFormat::Nimbus),
+            "lodestar" => Ok(ClientFormat::Lodestar),
+            "geth" => Ok(ClientFormat::Geth),
+            "reth" => Ok(ClientFormat::Reth),
+            _ => Err("Unknown client format"),
+        }
+    }
+}
+
+impl ClientFormat {
+    /// The values accepted by the `--format` flag of the `key` commands.
+    pub const VARIANTS: [&'static str; 7] = [
+        "lighthouse",
+        "prysm",
+        "teku",
+        "nimbus",
+        "lodestar",
+        "geth",
+        "reth",
+    ];
+
+    /// Decodes the contents of a key file.
+    pub fn decode(self, bytes: &[u8]) -> Result<CombinedKey, &'static str> {
+        let text = || {
+            std::str::from_utf8(bytes)
+                .map(str::trim)
+                .map_err(|_| "Key file is not valid text")
+        };
+        // Decoded secrets are wiped when dropped.
+        let hex_bytes = |text: &str| {
+            hex::decode(text.trim_start_matches("0x"))
+                .map(Zeroizing::new)
+                .map_err(|_| "Key file is not valid hex")
+        };
+        match self {
+            ClientFormat::Lighthouse => {
+                KeyType::Secp256k1.key_from_bytes(&mut Zeroizing::new(bytes.to_vec()))
+            }
+            ClientFormat::Prysm => {
+                let mut key_bytes = hex_bytes(text()?)?;
+                if key_bytes.len() == 32 {
+                    KeyType::Secp256k1.key_from_bytes(&mut key_bytes)
+                } else {
+                    from_protobuf(&key_bytes)
+                }
+            }
+            ClientFormat::Teku => from_protobuf(&hex_bytes(text()?)?),
+            ClientFormat::Nimbus => Err(NIMBUS_UNSUPPORTED),
+            ClientFormat::Lodestar => {
+                let peer_id_json: serde_json::Value =
+                    serde_json::from_str(text()?).map_err(|_| "Invalid peer-id.json")?;
+                let private_key = peer_id_json
+                    .get("privKey")
+                    .and_then(|private_key| private_key.as_str())
+                    .ok_or("peer-id.json has no privKey")?;
+                let key = from_protobuf(&Zeroizing::new(
+                    STANDARD
+                        .decode(private_key)
+                        .map_err(|_| "Invalid privKey base64")?,
+                ))?;
+                // Catch files whose id was edited independently of the key.
+                if let Some(id) = peer_id_json.get("id").and_then(|id| id.as_str()) {
+                    if id != key.public().as_peer_id().to_string() {
+                        return Err("The id in peer-id.json does not match privKey");
+                    }
+                }
+                Ok(key)
+            }
+            ClientFormat::Geth | ClientFormat::Reth => {
+                KeyType::Secp256k1.key_from_bytes(&mut hex_bytes(text()?)?)
+            }
+        }
+    }
+
+    /// Encodes a key as the contents of a key file.
+    pub fn encode(self, key: &CombinedKey) -> Result<Vec<u8>, &'static str> {
+        let is_secp256k1 = matches!(key, CombinedKey::Secp256k1(_));
+        match self {
+            ClientFormat::Nimbus => Err(NIMBUS_UNSUPPORTED),
+            ClientFormat::Teku => Ok(format!("0x{}", hex::encode(to_protobuf(key))).into_bytes()),
+            ClientFormat::Lodestar => {
+                let public_key = key.to_libp2p().public();
+                let peer_id_json = json!({
+                    "id": public_key.to_peer_id().to_string(),
+                    "privKey": STANDARD.encode(to_protobuf(key)),
+                    "pubKey": STANDARD.encode(public_key.encode_protobuf()),
+                });
+                Ok(serde_json::to_vec_pretty(&peer_id_json).expect("valid json"))
+            }
+            _ if !is_secp256k1 => Err("This client only supports secp256k1 node keys"),
+            ClientFormat::Lighthouse => Ok(key.encode()),
+            ClientFormat::Prysm | ClientFormat::Geth | ClientFormat::Reth => {
+                Ok(hex::encode(key.encode()).into_bytes())
+            }
+        }
+    }
+}
+
+const NIMBUS_UNSUPPORTED: &str =
+    "Nimbus netkey files are encrypted keystores, use --keystore with --password-file";
+
+fn from_protobuf(bytes: &[u8]) -> Result<CombinedKey, &'static str> {
+    let keypair =
+        Keypair::from_protobuf_encoding(bytes).map_err(|_| "Invalid protobuf libp2p key")?;
+    CombinedKey::from_libp2p(keypair)
+}
+
+fn to_protobuf(key: &CombinedKey) -> Vec<u8> {
+    key.to_libp2p()
+        .to_protobuf_encoding()
+        .expect("secp256k1 and ed25519 keys can be encoded")
+}
+
+/// Standard encodings of a private key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEncoding {
+    /// The hex encoded secret.
+    Hex,
+    /// The protobuf encoding of a libp2p `Keypair`.
+    Protobuf,
+    /// A PKCS#8 `PRIVATE KEY` PEM. SEC1 `EC PRIVATE KEY` PEMs are also read.
+    Pem,
+    /// A SEC1 `EC PRIVATE KEY` PEM. Only secp256k1 keys can be written.
+    Sec1Pem,
+    /// A JSON Web Key (RFC 7517, RFC 8037 for ed25519).
+    Jwk,
+}
+
+impl FromStr for KeyEncoding {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(KeyEncoding::Hex),
+            "protobuf" => Ok(KeyEncoding::Protobuf),
+            "pem" => Ok(KeyEncoding::Pem),
+            "sec1-pem" => Ok(KeyEncoding::Sec1Pem),
+            "jwk" => Ok(KeyEncoding::Jwk),
+            _ => Err("Unknown key encoding"),
+        }
+    }
+}
+
+impl KeyEncoding {
+    /// The values accepted by the `--from` and `--to` flags of `key convert`.
+    pub const VARIANTS: [&'static str; 5] = ["hex", "protobuf", "pem", "sec1-pem", "jwk"];
+
+    /// Decodes a key. The key type is only needed for ambiguous hex secrets.
+    pub fn decode(
+        self,
+        bytes: &[u8],
+        key_type: Option<KeyType>,
+    ) -> Result<CombinedKey, &'static str> {
+        let text = || {
+            std::str::from_utf8(bytes)
+                .map(str::trim)
+                .map_err(|_| "Key is not valid text")
+        };
+        match self {
+            KeyEncoding::Hex => {
+                let key_bytes =
+                    hex::decode(text()?.trim_start_matches("0x")).map_err(|_| "Invalid hex key")?;
+                keys::key_from_bytes(key_bytes, key_type)
+            }
+            KeyEncoding::Protobuf => from_protobuf(bytes),
+            KeyEncoding::Pem | KeyEncoding::Sec1Pem => {
+                let pem = text()?;
+                if pem.contains("EC PRIVATE KEY") {
+                    let secret =
+                        k256::SecretKey::from_sec1_pem(pem).map_err(|_| "Invalid SEC1 PEM")?;
+                    Ok(CombinedKey::Secp256k1(secret.into()))
+                } else if let Ok(secret) = k256::SecretKey::from_pkcs8_pem(pem) {
+                    Ok(CombinedKey::Secp256k1(secret.into()))
+                } else {
+                    ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+                        .map(CombinedKey::from)
+                        .map_err(|_| {
+                            "Invalid PKCS#8 PEM, only secp256k1 and ed25519 keys are supported"
+                        })
+                }
+            }
+            KeyEncoding::Jwk => from_jwk(text()?),
+        }
+    }
+
+    /// Encodes a key.
+    pub fn encode(self, key: &CombinedKey) -> Result<Vec<u8>, &'static str> {
+        match (self, key) {
+            (KeyEncoding::Hex, _) => Ok(hex::encode(key.encode()).into_bytes()),
+            (KeyEncoding::Protobuf, _) => Ok(to_protobuf(key)),
+            (KeyEncoding::Pem, CombinedKey::Secp256k1(key)) => k256::SecretKey::from(key)
+                .to_pkcs8_pem(LineEnding::LF)
+                .map(|pem| pem.as_bytes().to_vec())
+                .map_err(|_| "Cannot encode PEM"),
+            (KeyEncoding::Pem, CombinedKey::Ed25519(key)) => key
+                .to_pkcs8_pem(LineEnding::LF)
+                .map(|pem| pem.as_bytes().to_vec())
+                .map_err(|_| "Cannot encode PEM"),
+            (KeyEncoding::Sec1Pem, CombinedKey::Secp256k1(key)) => k256::SecretKey::from(key)
+                .to_sec1_pem(LineEnding::LF)
+                .map(|pem| pem.as_bytes().to_vec())
+                .map_err(|_| "Cannot encode PEM"),
+            (KeyEncoding::Sec1Pem, CombinedKey::Ed25519(_)) => {
+                Err("SEC1 PEMs can only hold secp256k1 keys")
+            }
+            (KeyEncoding::Jwk, _) => {
+                Ok(serde_json::to_vec_pretty(&to_jwk(key)).expect("valid json"))
+            }
+        }
+    }
+}
+
+fn to_jwk(key: &CombinedKey) -> serde_json::Value {
+    let b64 = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+    match key {
+        CombinedKey::Secp256k1(key) => {
+            let point = key.verifying_key().to_encoded_point(false);
+            json!({
+                "kty": "EC",
+                "crv": "secp256k1",
+                "x": b64(point.x().expect("uncompressed point")),
+                "y": b64(point.y().expect("uncompressed point")),
+                "d": b64(&key.to_bytes()),
+            })
+        }
+        CombinedKey::Ed25519(key) => json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": b64(key.verifying_key().as_bytes()),
+            "d": b64(key.as_bytes()),
+        }),
+    }
+}
+
+fn from_jwk(text: &str) -> Result<CombinedKey, &'static str> {
+    let jwk: serde_json::Value = serde_json::from_str(text).map_err(|_| "Invalid JWK")?;
+    let member = |name: &str| jwk.get(name).and_then(|value| value.as_str());
+    let mut secret = Zeroizing::new(
+        URL_SAFE_NO_PAD
+            .decode(member("d").ok_or("JWK has no private key")?)
+            .map_err(|_| "Invalid JWK base64")?,
+    );
+    let key = match (member("kty"), member("crv")) {
+        (Some("EC"), Some("secp256k1")) => KeyType::Secp256k1.key_from_bytes(&mut secret)?,
+        (Some("OKP"), Some("Ed25519")) => KeyType::Ed25519.key_from_bytes(&mut secret)?,
+        _ => return Err("Only secp256k1 and Ed25519 JWKs are supported"),
+    };
+    // The public members are optional but must agree with the private key.
+    let expected = to_jwk(&key);
+    for name in ["x", "y"] {
+        if member(name).is_some_and(|value| Some(value) != expected[name].as_str()) {
+            return Err("The JWK public key does not match its private key");
+        }
+    }
+    Ok(key)
+}
+
+/// The public identity behind a key.
+fn identity(key: &CombinedKey) -> String {
+    format!(
+        "Key Type: {}\nNodeId: {}\nLibp2p PeerId: {}",
+        KeyType::of(&key.public()),
+        hex::encode(NodeId::from(key.public()).raw()),
+        key.public().as_peer_id()
+    )
+}
+
+/// Prints the public identity behind a key.
+fn print_identity(key: &CombinedKey) {
+    println!("{}", identity(key));
+}
+
+/// Prints the identity behind a key, and the key itself with `--print-secret`.
+fn print_key(key: &CombinedKey, matches: &clap::ArgMatches) {
+    if matches.get_flag("print-secret") {
+        println!("Private Key: {}", hex::encode(key.encode()));
+    }
+    print_identity(key);
+}
+
+/// Reads the password given by `--password-file`.
+fn password(matches: &clap::ArgMatches) -> Result<Zeroizing<String>, &'static str> {
+    let password_file = matches
+        .get_one::<String>("password-file")
+        .ok_or("A --password-file is required for encrypted keys")?;
+    keystore::read_password_file(Path::new(password_file))
+}
+
+/// Generates a new key, optionally encrypted with a password.
+pub fn new(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let key = KeyType::from_matches(matches)?
+        .unwrap_or(KeyType::Secp256k1)
+        .generate();
+    save_key(&key, matches)
+}
+
+/// Derives a key from a seed. As the key can be derived again, it is only saved or printed when
+/// asked to.
+pub fn derive(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let key = derive::key_from_matches(matches, KeyType::from_matches(matches)?)?
+        .ok_or("A --seed is required")?;
+    if matches.contains_id("out") || matches.get_flag("encrypt") || matches.get_flag("print-secret")
+    {
+        save_key(&key, matches)
+    } else {
+        print_identity(&key);
+        Ok(())
+    }
+}
+
+/// Writes a key to `--out` or stdout as given by `--encrypt` and `--print-secret`.
+pub(crate) fn save_key(key: &CombinedKey, matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let encrypted = if matches.get_flag("encrypt") {
+        let kdf = match matches.get_one::<String>("kdf").map(String::as_str) {
+            Some("pbkdf2") => KdfParams::pbkdf2(),
+            _ => KdfParams::scrypt(),
+        };
+        Some(Keystore::encrypt(key, &password(matches)?, kdf)?.to_json())
+    } else {
+        None
+    };
+    match (matches.get_one::<String>("out"), encrypted) {
+        (Some(out), encrypted) => {
+            let bytes = Zeroizing::new(encrypted.unwrap_or_else(|| key.encode()));
+            keys::write_secret_file(Path::new(out), &bytes)?;
+            print_key(key, matches);
+        }
+        (None, Some(keystore)) => {
+            // Only the keystore goes to stdout, so it can be redirected to a file.
+            eprintln!("{}", identity(key));
+            std::io::stdout()
+                .write_all(&keystore)
+                .map_err(|_| "Cannot write to stdout")?
+        }
+        (None, None) if matches.get_flag("print-secret") => print_key(key, matches),
+        (None, None) => return Err("Use --out to save the key or --print-secret to print it"),
+    }
+    Ok(())
+}
+
+/// Reads a client key file and prints the key it holds.
+pub fn import(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let format = matches
+        .get_one::<String>("format")
+        .expect("Must supply a format")
+        .parse::<ClientFormat>()?;
+    let file = matches
+        .get_one::<String>("file")
+        .expect("Must supply a key file");
+    let bytes = std::fs::read(file).map_err(|_| "Cannot read key file")?;
+    let key = match format {
+        ClientFormat::Nimbus => Keystore::from_json(&bytes)?.decrypt(&password(matches)?, None)?,
+        format => format.decode(&bytes)?,
+    };
+    print_key(&key, matches);
+    Ok(())
+}
+
+/// Encodes a key in a client's format. Nimbus keys are encrypted with `--password-file`.
+pub fn encode_client_key(
+    key: &CombinedKey,
+    format: ClientFormat,
+    matches: &clap::ArgMatches,
+) -> Result<Zeroizing<Vec<u8>>, &'static str> {
+    Ok(Zeroizing::new(match format {
+        ClientFormat::Nimbus => {
+            Keystore::encrypt_libp2p(key, &password(matches)?, KdfParams::pbkdf2())?.to_json()
+        }
+        format => format.encode(key)?,
+    }))
+}
+
+/// Writes the key given by `--private-key` or `--key-file` in a client's format.
+pub fn export(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let format = matches
+        .get_one::<String>("format")
+        .expect("Must supply a format")
+        .parse::<ClientFormat>()?;
+    let key = keys::load_key(matches)?.ok_or("A key is required to export")?;
+    let bytes = encode_client_key(&key, format, matches)?;
+    match matches.get_one::<String>("out") {
+        Some(out) => {
+            keys::write_secret_file(Path::new(out), &bytes)?;
+            print_identity(&key);
+        }
+        None => std::io::stdout()
+            .write_all(&bytes)
+            .map_err(|_| "Cannot write to stdout")?,
+    }
+    Ok(())
+}
+
+/// Converts a key between standard encodings.
+pub fn convert(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let from = matches
+        .get_one::<String>("from")
+        .expect("Must supply an input encoding")
+        .parse::<KeyEncoding>()?;
+    let to = matches
+        .get_one::<String>("to")
+        .expect("Must supply an output encoding")
+        .parse::<KeyEncoding>()?;
+    let bytes = match matches.get_one::<String>("input").map(String::as_str) {
+        Some("-") | None => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|_| "Cannot read stdin")?;
+            bytes
+        }
+        Some(file) => std::fs::read(file).map_err(|_| "Cannot read key file")?,
+    };
+    let key = from.decode(&bytes, KeyType::from_matches(matches)?)?;
+    let mut bytes = Zeroizing::new(to.encode(&key)?);
+    match matches.get_one::<String>("out") {
+        Some(out) => keys::write_secret_file(Path::new(out), &bytes)?,
+        None => {
+            if to != KeyEncoding::Protobuf && !bytes.ends_with(b"\n") {
+                bytes.push(b'\n');
+            }
+            std::io::stdout()
+                .write_all(&bytes)
+                .map_err(|_| "Cannot write to stdout")?
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_formats_keep_node_id() {
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let node_id = NodeId::from(key.public());
+            let is_ed25519 = matches!(key, CombinedKey::Ed25519(_));
+            for format in ClientFormat::VARIANTS {
+                let format = format.parse::<ClientFormat>().unwrap();
+                let Ok(bytes) = format.encode(&key) else {
+                    // Nimbus keys are keystores, the other clients only use secp256k1 keys.
+                    let secp256k1_only =
+                        !matches!(format, ClientFormat::Teku | ClientFormat::Lodestar);
+                    assert!(
+                        format == ClientFormat::Nimbus || (is_ed25519 && secp256k1_only),
+                        "{:?}",
+                        format
+                    );
+                    continue;
+                };
+                let decoded = format.decode(&bytes).unwrap();
+                assert_eq!(NodeId::from(decoded.public()), node_id, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prysm_reads_protobuf_keys() {
+        let key = CombinedKey::generate_secp256k1();
+        let protobuf_hex = hex::encode(to_protobuf(&key));
+        let decoded = ClientFormat::Prysm.decode(protobuf_hex.as_bytes()).unwrap();
+        assert_eq!(decoded.encode(), key.encode());
+    }
+
+    #[test]
+    fn test_key_encodings_round_trip() {
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let key_type = KeyType::of(&key.public());
+            for encoding in KeyEncoding::VARIANTS {
+                let encoding = encoding.parse::<KeyEncoding>().unwrap();
+                let Ok(bytes) = encoding.encode(&key) else {
+                    assert_eq!(encoding, KeyEncoding::Sec1Pem);
+                    continue;
+                };
+                let decoded = encoding.decode(&bytes, Some(key_type)).unwrap();
+                assert_eq!(decoded.encode(), key.encode(), "{:?}", encoding);
+                assert_eq!(KeyType::of(&decoded.public()), key_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_jwk_rejects_mismatched_public_key() {
+        let mut jwk = to_jwk(&CombinedKey::generate_secp256k1());
+        jwk["x"] = to_jwk(&CombinedKey::generate_secp256k1())["x"].clone();
+        assert!(from_jwk(&jwk.to_string()).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Loads node keys from the sources given on the command line.
+use crate::keystore;
+use enr::{CombinedKey, CombinedPublicKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// The signature schemes a node key can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    Secp256k1,
+    Ed25519,
+}
+
+impl KeyType {
+    /// The values accepted by the `--key-type` CLI flag.
+    pub const VARIANTS: [&'static str; 2] = ["secp256k1", "ed25519"];
+
+    /// Reads `--key-type`, if given.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Option<Self>, &'static str> {
+        matches
+            .get_one::<String>("key-type")
+            .map(|key_type| key_type.parse())
+            .transpose()
+    }
+
+    /// The type of a public key.
+    pub fn of(public_key: &CombinedPublicKey) -> Self {
+        match public_key {
+            CombinedPublicKey::Secp256k1(_) => KeyType::Secp256k1,
+            CombinedPublicKey::Ed25519(_) => KeyType::Ed25519,
+        }
+    }
+
+    /// Generates a random key of this type.
+    pub fn generate(self) -> CombinedKey {
+        match self {
+            KeyType::Secp256k1 => CombinedKey::generate_secp256k1(),
+            KeyType::Ed25519 => CombinedKey::generate_ed25519(),
+        }
+    }
+
+    /// Decodes the raw bytes of a private key of this type.
+    pub fn key_from_bytes(self, key_bytes: &mut [u8]) -> Result<CombinedKey, &'static str> {
+        match self {
+            KeyType::Secp256k1 => CombinedKey::secp256k1_from_bytes(key_bytes)
+                .map_err(|_| "Invalid secp256k1 private key"),
+            KeyType::Ed25519 => CombinedKey::ed25519_from_bytes(key_bytes)
+                .map_err(|_| "Invalid ed25519 private key"),
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "secp256k1" => Ok(KeyType::Secp256k1),
+            "ed25519" => Ok(KeyType::Ed25519),
+            _ => Err("Unknown key type"),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyType::Secp256k1 => write!(f, "secp256k1"),
+            KeyType::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+/// The longest secret read from a file or stdin: a hex encoded key with a `0x` prefix and
+/// surrounding whitespace fits with room to spare.
+const MAX_SECRET_LEN: usize = 256;
+
+/// Reads a secret into a fixed buffer, so no reallocation leaves copies of it behind. Returns the
+/// buffer and the number of bytes read.
+fn read_secret(
+    mut reader: impl Read,
+) -> Result<(Zeroizing<[u8; MAX_SECRET_LEN]>, usize), &'static str> {
+    let mut buffer = Zeroizing::new([0u8; MAX_SECRET_LEN]);
+    let mut len = 0;
+    loop {
+        if len == MAX_SECRET_LEN {
+            return match reader.read(&mut [0u8; 1]) {
+                Ok(0) => Ok((buffer, len)),
+                _ => Err("The private key is too long"),
+            };
+        }
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => return Ok((buffer, len)),
+            Ok(read) => len += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(_) => return Err("Cannot read the private key"),
+        }
+    }
+}
+
+/// The hex encoded key given by `--private-key`, `--private-key-env` or `--private-key-stdin`.
+fn private_key_hex(matches: &clap::ArgMatches) -> Result<Option<Zeroizing<Vec<u8>>>, &'static str> {
+    if let Some(priv_key) = matches.get_one::<String>("private-key") {
+        Ok(Some(Zeroizing::new(priv_key.as_bytes().to_vec())))
+    } else if let Some(var) = matches.get_one::<String>("private-key-env") {
+        std::env::var(var)
+            .map(|priv_key| Some(Zeroizing::new(priv_key.into_bytes())))
+            .map_err(|_| "The --private-key-env variable is not set")
+    } else if matches.get_flag("private-key-stdin") {
+        let (buffer, len) = read_secret(std::io::stdin().lock())?;
+        Ok(Some(Zeroizing::new(buffer[..len].to_vec())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decodes a hex key into a buffer of its exact size.
+fn decode_hex_key(priv_key: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let hex = std::str::from_utf8(priv_key)
+        .map_err(|_| "Invalid private key hex bytes")?
+        .trim()
+        .trim_start_matches("0x");
+    let mut key_bytes = Zeroizing::new(vec![0u8; hex.len() / 2]);
+    hex::decode_to_slice(hex, &mut key_bytes).map_err(|_| "Invalid private key hex bytes")?;
+    Ok(std::mem::take(&mut *key_bytes))
+}
+
+/// Imports the key given by `--private-key`, `--private-key-env`, `--private-key-stdin`,
+/// `--key-file` or `--keystore`, if any, as the type given by `--key-type`.
+pub fn load_key(matches: &clap::ArgMatches) -> Result<Option<CombinedKey>, &'static str> {
+    load_key_as(matches, KeyType::from_matches(matches)?)
+}
+
+/// Imports the key given on the command line like [`load_key`], as the type `key_type`.
+pub fn load_key_as(
+    matches: &clap::ArgMatches,
+    key_type: Option<KeyType>,
+) -> Result<Option<CombinedKey>, &'static str> {
+    if let Some(priv_key) = private_key_hex(matches)? {
+        key_from_bytes(decode_hex_key(&priv_key)?, key_type).map(Some)
+    } else if let Some(key_file) = matches.get_one::<String>("key-file") {
+        read_key_file(Path::new(key_file), key_type).map(Some)
+    } else if let Some(keystore_file) = matches.get_one::<String>("keystore") {
+        let password_file = matches
+            .get_one::<String>("password-file")
+            .ok_or("--keystore requires --password-file")?;
+        let password = keystore::read_password_file(Path::new(password_file))?;
+        keystore::read_keystore(Path::new(keystore_file), &password, key_type).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Writes secret key material to a new file that only the owner can read. Existing files are never
+/// overwritten.
+pub fn write_secret_file(path: &Path, bytes: &[u8]) -> Result<(), &'static str> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => "Refusing to overwrite an existing key file",
+        _ => "Cannot create key file",
+    })?;
+    file.write_all(bytes).map_err(|_| "Cannot write key file")
+}
+
+/// Reads a file holding the raw bytes of a private key.
+pub fn read_key_file(path: &Path, key_type: Option<KeyType>) -> Result<CombinedKey, &'static str> {
+    let file = File::open(path).map_err(|_| "Cannot find key-file")?;
+    let (buffer, len) = read_secret(file)?;
+    key_from_bytes(buffer[..len].to_vec(), key_type)
+}
+
+/// Decodes a private key, zeroizing the bytes. Without a key type, secp256k1 is tried before
+/// ed25519, so 32 byte ed25519 keys must be given a type.
+pub fn key_from_bytes(
+    key_bytes: Vec<u8>,
+    key_type: Option<KeyType>,
+) -> Result<CombinedKey, &'static str> {
+    let mut key_bytes = Zeroizing::new(key_bytes);
+    match key_type {
+        Some(key_type) => key_type.key_from_bytes(&mut key_bytes),
+        None => KeyType::Secp256k1
+            .key_from_bytes(&mut key_bytes)
+            .or_else(|_| KeyType::Ed25519.key_from_bytes(&mut key_bytes))
+            .map_err(|_| "Invalid private key"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enr_ext::{CombinedKeyPublicExt, EnrExt};
+    use crate::spec::EnrSpec;
+    use crate::Enr;
+    use enr::EnrKey;
+
+    #[test]
+    fn test_ed25519_key_end_to_end() {
+        let secret = [7u8; 32];
+        let key = key_from_bytes(secret.to_vec(), Some(KeyType::Ed25519)).unwrap();
+        assert_eq!(KeyType::of(&key.public()), KeyType::Ed25519);
+        // Without a type the same bytes are read as secp256k1.
+        let untyped = key_from_bytes(secret.to_vec(), None).unwrap();
+        assert_eq!(KeyType::of(&untyped.public()), KeyType::Secp256k1);
+
+        let spec = EnrSpec {
+            udp_port: Some(9000),
+            ..Default::default()
+        };
+        let enr = spec
+            .build(&key, false)
+            .unwrap()
+            .to_base64()
+            .parse::<Enr>()
+            .unwrap();
+        assert!(enr.verify());
+        assert_eq!(KeyType::of(&enr.public_key()), KeyType::Ed25519);
+        assert_eq!(enr.enode_id(), None);
+
+        let libp2p_peer_id = libp2p_identity::Keypair::ed25519_from_bytes(secret)
+            .unwrap()
+            .public()
+            .to_peer_id();
+        assert_eq!(enr.peer_id(), libp2p_peer_id);
+        assert_eq!(key.public().as_peer_id(), libp2p_peer_id);
+    }
+
+    #[test]
+    fn test_secrets_are_read_into_fixed_buffers() {
+        let hex = format!("0x{}\n", "07".repeat(32));
+        let (buffer, len) = read_secret(hex.as_bytes()).unwrap();
+        assert_eq!(decode_hex_key(&buffer[..len]).unwrap(), [7u8; 32]);
+        assert!(read_secret(&[b'0'; MAX_SECRET_LEN][..]).is_ok());
+        assert!(read_secret(&[b'0'; MAX_SECRET_LEN + 1][..]).is_err());
+        assert!(decode_hex_key(b"0x123").is_err());
+    }
+
+    #[test]
+    fn test_secret_files_are_private_and_never_overwritten() {
+        let path = std::env::temp_dir().join(format!("enr-cli-key-{}.dat", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        write_secret_file(&path, b"secret").unwrap();
+        assert!(write_secret_file(&path, b"other").is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,413 @@
+//! Password-encrypted node keys, using the JSON layout of EIP-2335 keystores.
+//!
+//! The secret is encrypted with AES-128-CTR under a key derived from the password with scrypt or
+//! PBKDF2. Nimbus `netkey` files use the same layout with a protobuf encoded libp2p key as the
+//! secret, and are read and written with [`Keystore::decrypt`] and [`Keystore::encrypt_libp2p`].
+use crate::enr_ext::CombinedKeyExt;
+use crate::keys::{self, KeyType};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use enr::{CombinedKey, EnrKey, EnrPublicKey};
+use libp2p_identity::Keypair;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use subtle::ConstantTimeEq;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// The version of keystores holding a raw secret, as in EIP-2335.
+const KEYSTORE_VERSION: u32 = 4;
+/// The version of Nimbus netkey files.
+const NETKEY_VERSION: u32 = 1;
+
+/// The parameters of the key derivation function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: u32,
+        n: u32,
+        r: u32,
+        p: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        dklen: u32,
+        c: u32,
+        prf: String,
+        salt: String,
+    },
+}
+
+impl KdfParams {
+    /// Scrypt with the EIP-2335 parameters and a random salt.
+    pub fn scrypt() -> Self {
+        KdfParams::Scrypt {
+            dklen: 32,
+            n: 262144,
+            r: 8,
+            p: 1,
+            salt: hex::encode(rand::random::<[u8; 32]>()),
+        }
+    }
+
+    /// PBKDF2-HMAC-SHA256 with the EIP-2335 parameters and a random salt.
+    pub fn pbkdf2() -> Self {
+        KdfParams::Pbkdf2 {
+            dklen: 32,
+            c: 262144,
+            prf: "hmac-sha256".into(),
+            salt: hex::encode(rand::random::<[u8; 32]>()),
+        }
+    }
+
+    fn function(&self) -> &'static str {
+        match self {
+            KdfParams::Scrypt { .. } => "scrypt",
+            KdfParams::Pbkdf2 { .. } => "pbkdf2",
+        }
+    }
+
+    fn derive_key(&self, password: &[u8]) -> Result<Zeroizing<[u8; 32]>, &'static str> {
+        let mut derived_key = Zeroizing::new([0u8; 32]);
+        match self {
+            KdfParams::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                if *dklen != 32 || !n.is_power_of_two() {
+                    return Err("Unsupported scrypt parameters");
+                }
+                let salt = hex::decode(salt).map_err(|_| "Invalid keystore salt")?;
+                let params = scrypt::Params::new(n.trailing_zeros() as u8, *r, *p, 32)
+                    .map_err(|_| "Unsupported scrypt parameters")?;
+                scrypt::scrypt(password, &salt, &params, derived_key.as_mut())
+                    .map_err(|_| "Unsupported scrypt parameters")?;
+            }
+            KdfParams::Pbkdf2 {
+                dklen,
+                c,
+                prf,
+                salt,
+            } => {
+                if *dklen != 32 || prf != "hmac-sha256" {
+                    return Err("Unsupported pbkdf2 parameters");
+                }
+                let salt = hex::decode(salt).map_err(|_| "Invalid keystore salt")?;
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, &salt, *c, derived_key.as_mut());
+            }
+        }
+        Ok(derived_key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Module<P> {
+    pub function: String,
+    pub params: P,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmptyParams {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crypto {
+    pub kdf: Module<KdfParams>,
+    pub checksum: Module<EmptyParams>,
+    pub cipher: Module<CipherParams>,
+}
+
+/// An encrypted node key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub crypto: Crypto,
+    #[serde(default)]
+    pub description: String,
+    /// The hex encoded public key: compressed secp256k1, raw ed25519 or, for Nimbus netkeys, the
+    /// protobuf libp2p public key.
+    pub pubkey: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub uuid: String,
+    pub version: u32,
+}
+
+/// Prepares a password as EIP-2335 requires: NFKD normalised with control codes removed.
+fn normalize_password(password: &str) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(
+        password
+            .nfkd()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .into_bytes(),
+    )
+}
+
+/// Reads a password file, ignoring a trailing newline.
+pub fn read_password_file(path: &Path) -> Result<Zeroizing<String>, &'static str> {
+    let password =
+        Zeroizing::new(std::fs::read_to_string(path).map_err(|_| "Cannot read password file")?);
+    Ok(Zeroizing::new(
+        password.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
+fn checksum(derived_key: &[u8; 32], cipher_message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(&derived_key[16..]);
+    hasher.update(cipher_message);
+    hasher.finalize().into()
+}
+
+fn apply_cipher(derived_key: &[u8; 32], iv: &[u8], message: &mut [u8]) -> Result<(), &'static str> {
+    let mut cipher =
+        Aes128Ctr::new_from_slices(&derived_key[..16], iv).map_err(|_| "Invalid keystore iv")?;
+    cipher.apply_keystream(message);
+    Ok(())
+}
+
+impl Keystore {
+    /// Encrypts the raw secret of a key.
+    pub fn encrypt(
+        key: &CombinedKey,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, &'static str> {
+        let pubkey = hex::encode(key.public().encode());
+        Self::encrypt_secret(
+            Zeroizing::new(key.encode()),
+            pubkey,
+            password,
+            kdf,
+            KEYSTORE_VERSION,
+        )
+    }
+
+    /// Encrypts the protobuf libp2p encoding of a key, as Nimbus netkey files do.
+    pub fn encrypt_libp2p(
+        key: &CombinedKey,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, &'static str> {
+        let keypair = key.to_libp2p();
+        let secret = Zeroizing::new(
+            keypair
+                .to_protobuf_encoding()
+                .map_err(|_| "Cannot encode libp2p key")?,
+        );
+        let pubkey = hex::encode(keypair.public().encode_protobuf());
+        Self::encrypt_secret(secret, pubkey, password, kdf, NETKEY_VERSION)
+    }
+
+    fn encrypt_secret(
+        mut secret: Zeroizing<Vec<u8>>,
+        pubkey: String,
+        password: &str,
+        kdf: KdfParams,
+        version: u32,
+    ) -> Result<Self, &'static str> {
+        let derived_key = kdf.derive_key(&normalize_password(password))?;
+        let iv = rand::random::<[u8; 16]>();
+        apply_cipher(&derived_key, &iv, &mut secret)?;
+        Ok(Keystore {
+            crypto: Crypto {
+                kdf: Module {
+                    function: kdf.function().into(),
+                    params: kdf,
+                    message: String::new(),
+                },
+                checksum: Module {
+                    function: "sha256".into(),
+                    params: EmptyParams {},
+                    message: hex::encode(checksum(&derived_key, &secret)),
+                },
+                cipher: Module {
+                    function: "aes-128-ctr".into(),
+                    params: CipherParams {
+                        iv: hex::encode(iv),
+                    },
+                    message: hex::encode(&secret),
+                },
+            },
+            description: String::new(),
+            pubkey,
+            path: (version == KEYSTORE_VERSION).then(String::new),
+            uuid: uuid::Uuid::new_v4().to_string(),
+            version,
+        })
+    }
+
+    /// Decrypts the key, checking it against the stored public key. A raw 32 byte secret is read as
+    /// the type of the stored public key, or else as `key_type`.
+    pub fn decrypt(
+        &self,
+        password: &str,
+        key_type: Option<KeyType>,
+    ) -> Result<CombinedKey, &'static str> {
+        if self.crypto.cipher.function != "aes-128-ctr" {
+            return Err("Unsupported keystore cipher");
+        }
+        let derived_key = self
+            .crypto
+            .kdf
+            .params
+            .derive_key(&normalize_password(password))?;
+        let mut secret = Zeroizing::new(
+            hex::decode(&self.crypto.cipher.message).map_err(|_| "Invalid keystore cipher text")?,
+        );
+        let stored_checksum =
+            hex::decode(&self.crypto.checksum.message).map_err(|_| "Invalid keystore checksum")?;
+        // Compared in constant time, so the checksum of a guessed password leaks nothing.
+        if !bool::from(checksum(&derived_key, &secret).ct_eq(&stored_checksum)) {
+            return Err("Invalid password");
+        }
+        let iv = hex::decode(&self.crypto.cipher.params.iv).map_err(|_| "Invalid keystore iv")?;
+        apply_cipher(&derived_key, &iv, &mut secret)?;
+
+        let pubkey = hex::decode(&self.pubkey).map_err(|_| "Invalid keystore pubkey")?;
+        let key = if secret.len() == 32 {
+            // The length of the public key tells the key types apart.
+            let key_type = match pubkey.len() {
+                33 => Some(KeyType::Secp256k1),
+                32 => Some(KeyType::Ed25519),
+                _ => key_type,
+            };
+            keys::key_from_bytes(secret.to_vec(), key_type)?
+        } else {
+            let keypair =
+                Keypair::from_protobuf_encoding(&secret).map_err(|_| "Invalid keystore secret")?;
+            CombinedKey::from_libp2p(keypair)?
+        };
+
+        let public_key = key.public();
+        let libp2p_public_key = key.to_libp2p().public().encode_protobuf();
+        if !pubkey.is_empty() && pubkey != public_key.encode() && pubkey != libp2p_public_key {
+            return Err("The keystore pubkey does not match its secret");
+        }
+        Ok(key)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, &'static str> {
+        serde_json::from_slice(bytes).map_err(|_| "Invalid keystore JSON")
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("valid json")
+    }
+}
+
+/// Reads and decrypts a keystore file.
+pub fn read_keystore(
+    path: &Path,
+    password: &str,
+    key_type: Option<KeyType>,
+) -> Result<CombinedKey, &'static str> {
+    let bytes = std::fs::read(path).map_err(|_| "Cannot read keystore")?;
+    Keystore::from_json(&bytes)?.decrypt(password, key_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light_scrypt() -> KdfParams {
+        KdfParams::Scrypt {
+            dklen: 32,
+            n: 16,
+            r: 8,
+            p: 1,
+            salt: hex::encode([1u8; 32]),
+        }
+    }
+
+    #[test]
+    fn test_eip2335_test_vector() {
+        // The scrypt test vector from EIP-2335, whose secret is a valid secp256k1 scalar.
+        let keystore = r#"{
+            "crypto": {
+                "kdf": {"function": "scrypt", "params": {"dklen": 32, "n": 262144, "p": 1, "r": 8, "salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"}, "message": ""},
+                "checksum": {"function": "sha256", "params": {}, "message": "d2217fe5f3e9a1e34581ef8a78f7c9928e436d36dacc5e846690a5581e8ea484"},
+                "cipher": {"function": "aes-128-ctr", "params": {"iv": "264daa3f303d7259501c93d997d84fe6"}, "message": "06ae90d55fe0a6e9c5c3bc5b170827b2e5cce3929ed3f116c2811e6366dfe20f"}
+            },
+            "description": "This is a test keystore that uses scrypt to secure the secret.",
+            "pubkey": "",
+            "path": "m/12381/60/3141592653/589793238",
+            "uuid": "1d85ae20-35c5-4611-98e8-aa14a633906f",
+            "version": 4
+        }"#;
+        let password = "\u{1d531}\u{1d522}\u{1d530}\u{1d531}\u{1d52d}\u{1d51e}\u{1d530}\u{1d530}\u{1d534}\u{1d52c}\u{1d52f}\u{1d521}\u{1f511}";
+        let keystore = Keystore::from_json(keystore.as_bytes()).unwrap();
+        let key = keystore
+            .decrypt(password, Some(KeyType::Secp256k1))
+            .unwrap();
+        assert_eq!(
+            hex::encode(key.encode()),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(
+            keystore.decrypt("wrong", None).err(),
+            Some("Invalid password")
+        );
+        let mut uppercase = keystore.clone();
+        uppercase.crypto.checksum.message = uppercase.crypto.checksum.message.to_uppercase();
+        assert_eq!(
+            uppercase
+                .decrypt(password, Some(KeyType::Secp256k1))
+                .unwrap()
+                .encode(),
+            key.encode()
+        );
+    }
+
+    #[test]
+    fn test_cipher_counter_is_128_bits() {
+        use aes::cipher::{BlockEncrypt, KeyInit};
+        // The counter carries from the low into the high 64 bits of the iv.
+        let derived_key = [3u8; 32];
+        let iv = [
+            0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let mut message = [0u8; 32];
+        apply_cipher(&derived_key, &iv, &mut message).unwrap();
+
+        let aes = aes::Aes128::new_from_slice(&derived_key[..16]).unwrap();
+        let mut counter = [0u8; 16];
+        counter[7] = 1;
+        let mut second_block = counter.into();
+        aes.encrypt_block(&mut second_block);
+        assert_eq!(message[16..], second_block[..]);
+    }
+
+    #[test]
+    fn test_keystores_round_trip() {
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let keystore = Keystore::encrypt(&key, "password", light_scrypt()).unwrap();
+            let json = keystore.to_json();
+            let decrypted = Keystore::from_json(&json)
+                .unwrap()
+                .decrypt("password", None)
+                .unwrap();
+            assert_eq!(decrypted.encode(), key.encode());
+
+            let netkey = Keystore::encrypt_libp2p(&key, "password", light_scrypt()).unwrap();
+            assert_eq!(
+                netkey.decrypt("password", None).unwrap().encode(),
+                key.encode()
+            );
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! # enr-cli
+//!
+//! A library and CLI for reading, building and maintaining Ethereum Node Records (ENRs). The
+//! `enr-cli` binary is a thin layer over the modules below.
+//!
+//! ## Building records
+//!
+//! [`spec::EnrSpec`] describes a record: its sequence number, addresses and ports, eth2 fields and
+//! custom fields. It can be loaded from a TOML, YAML or JSON file, merged with overrides and
+//! signed with a key.
+//!
+//! ```
+//! use enr_cli::keys::KeyType;
+//! use enr_cli::spec::EnrSpec;
+//!
+//! let spec = EnrSpec {
+//!     ip: Some("1.2.3.4".parse().unwrap()),
+//!     udp_port: Some(9000),
+//!     ..Default::default()
+//! };
+//! let enr = spec.build(&KeyType::Secp256k1.generate(), false).unwrap();
+//! assert_eq!(enr.udp4(), Some(9000));
+//! ```
+//!
+//! - [`field`]: custom fields given as `name=type:value`.
+//! - [`seq`]: the sequence number of a new version of a record.
+//! - [`local_addr`]: addresses and ports read from the local host.
+//! - [`offline`] and [`signer`]: records signed away from the CLI, offline or by a remote signer.
+//! - [`update`], [`rotate`] and [`fleet`]: changing existing records, moving a record to a new key
+//!   and re-signing the records of many nodes.
+//! - [`testnet`]: the keys, records and bootstrap files of a local test network.
+//!
+//! ## Keys
+//!
+//! - [`keys`]: node keys from files, the environment or stdin.
+//! - [`key_format`]: the key files of the different clients and standard key encodings.
+//! - [`keystore`]: password-encrypted keys in the EIP-2335 layout.
+//! - [`derive`](mod@derive) and [`grind`]: keys derived from a seed, or searched for a node id.
+//! - [`message`]: messages signed with a node key.
+//!
+//! ## Reading records
+//!
+//! - [`encoding`]: the ways a record can be serialized.
+//! - [`enode`], [`convert`] and [`enr_ext`]: enode URLs, libp2p peer ids and multiaddrs.
+//! - [`eth2_ext`]: the eth2 fork id and subnet bitfields.
+//! - [`scan`]: records found in arbitrary text such as client logs.
+//! - [`inspect_dir`]: the key and record stored by a client.
+//! - [`output`]: the output formats of the commands that print records.
+//! - [`build`]: the `build` command.
+
+use enr::{CombinedKey, Enr as EnrRaw};
+
+pub mod build;
+pub mod convert;
+pub mod derive;
+pub mod encoding;
+pub mod enode;
+pub mod enr_ext;
+pub mod eth2_ext;
+pub mod field;
+pub mod fleet;
+pub mod grind;
+pub mod inspect_dir;
+pub mod key_format;
+pub mod keys;
+pub mod keystore;
+pub mod local_addr;
+pub mod message;
+pub mod offline;
+pub mod output;
+pub mod rotate;
+pub mod scan;
+pub mod seq;
+pub mod signer;
+pub mod spec;
+pub mod testnet;
+pub mod update;
+
+pub type Enr = EnrRaw<CombinedKey>;
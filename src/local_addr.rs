@@ -0,0 +1,412 @@
+//! Fills the addresses and ports of a record from the local host: the addresses of its network
+//! interfaces, and the ports a client process listens on.
+use crate::spec::EnrSpec;
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The reach of an address, from most to least preferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Scope {
+    Global,
+    /// RFC 1918 and shared (100.64.0.0/10) IPv4 addresses, unique local and site-local IPv6
+    /// addresses.
+    Private,
+    LinkLocal,
+    Loopback,
+    /// Documentation, benchmarking and "this network" addresses, which are never picked.
+    Reserved,
+}
+
+fn scope(ip: &IpAddr) -> Scope {
+    match ip {
+        IpAddr::V4(ip) if ip.is_loopback() => Scope::Loopback,
+        IpAddr::V4(ip) if ip.is_link_local() => Scope::LinkLocal,
+        IpAddr::V4(ip)
+            if ip.is_private() || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64) =>
+        {
+            Scope::Private
+        }
+        IpAddr::V4(ip) => match ip.octets() {
+            [0, ..]
+            | [192, 0, 2, _]
+            | [198, 51, 100, _]
+            | [203, 0, 113, _]
+            | [198, 18..=19, ..] => Scope::Reserved,
+            _ => Scope::Global,
+        },
+        IpAddr::V6(ip) if ip.is_loopback() => Scope::Loopback,
+        IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => Scope::LinkLocal,
+        IpAddr::V6(ip)
+            if ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfec0 =>
+        {
+            Scope::Private
+        }
+        IpAddr::V6(ip) if ip.segments()[..2] == [0x2001, 0xdb8] => Scope::Reserved,
+        _ => Scope::Global,
+    }
+}
+
+/// The temporary (RFC 4941) and deprecated IPv6 addresses of the host, which change over time and
+/// make poor record addresses. Read from `/proc/net/if_inet6`, so none are known off Linux.
+fn unstable_ipv6_addresses() -> HashSet<Ipv6Addr> {
+    const IFA_F_TEMPORARY: u8 = 0x01;
+    const IFA_F_DEPRECATED: u8 = 0x20;
+    let Ok(table) = fs::read_to_string("/proc/net/if_inet6") else {
+        return HashSet::new();
+    };
+    table
+        .lines()
+        .filter_map(|line| {
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            let flags = u8::from_str_radix(columns.get(4)?, 16).ok()?;
+            let address = u128::from_str_radix(columns.first()?, 16).ok()?;
+            (flags & (IFA_F_TEMPORARY | IFA_F_DEPRECATED) != 0).then(|| Ipv6Addr::from(address))
+        })
+        .collect()
+}
+
+/// Picks the address of an `--ip`/`--ip6` value: a literal address, `auto` for the first global
+/// address of any interface, or `iface:<name>` for the first global address of an interface.
+/// Private, link-local and loopback addresses are only picked with `allow_private`, and only if
+/// there is no global address. Stable IPv6 addresses are preferred over temporary ones.
+fn resolve(value: &str, v6: bool, allow_private: bool) -> Result<Option<IpAddr>, String> {
+    let interface = match value {
+        "auto" => None,
+        value => match value.strip_prefix("iface:") {
+            Some(interface) => Some(interface),
+            None => return Ok(None),
+        },
+    };
+    let addresses = if_addrs::get_if_addrs()
+        .map_err(|e| format!("Cannot read the network interfaces: {}", e))?;
+    let unstable = unstable_ipv6_addresses();
+    let is_unstable = |ip: IpAddr| match ip {
+        IpAddr::V6(ip) => unstable.contains(&ip),
+        IpAddr::V4(_) => false,
+    };
+    let (name, ip) = addresses
+        .iter()
+        .filter(|address| interface.is_none_or(|interface| address.name == interface))
+        .filter(|address| address.ip().is_ipv6() == v6)
+        .filter(|address| match scope(&address.ip()) {
+            Scope::Global => true,
+            Scope::Reserved => false,
+            _ => allow_private,
+        })
+        .min_by_key(|address| (scope(&address.ip()), is_unstable(address.ip())))
+        .map(|address| (address.name.as_str(), address.ip()))
+        .ok_or_else(|| {
+            format!(
+                "No {} {} address found on {}{}",
+                if allow_private { "usable" } else { "global" },
+                if v6 { "IPv6" } else { "IPv4" },
+                interface.map_or("any interface".to_string(), |i| format!("interface {}", i)),
+                if allow_private {
+                    ""
+                } else {
+                    ", use --allow-private-ip to accept private addresses"
+                },
+            )
+        })?;
+    eprintln!("Using {} of {}", ip, name);
+    Ok(Some(ip))
+}
+
+/// Reads `--ip`, resolving `auto` and `iface:<name>`.
+pub fn ip4_from_matches(matches: &clap::ArgMatches) -> Result<Option<Ipv4Addr>, String> {
+    let Some(value) = matches.get_one::<String>("ip") else {
+        return Ok(None);
+    };
+    match resolve(value, false, matches.get_flag("allow-private-ip"))? {
+        Some(IpAddr::V4(ip)) => Ok(Some(ip)),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| "Invalid IPv4 address".into()),
+    }
+}
+
+/// Reads `--ip6`, resolving `auto` and `iface:<name>`.
+pub fn ip6_from_matches(matches: &clap::ArgMatches) -> Result<Option<Ipv6Addr>, String> {
+    let Some(value) = matches.get_one::<String>("ip6") else {
+        return Ok(None);
+    };
+    match resolve(value, true, matches.get_flag("allow-private-ip"))? {
+        Some(IpAddr::V6(ip)) => Ok(Some(ip)),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| "Invalid IPv6 address".into()),
+    }
+}
+
+/// The ports a process has bound, excluding sockets bound to loopback addresses.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ListeningPorts {
+    pub tcp: BTreeSet<u16>,
+    pub tcp6: BTreeSet<u16>,
+    pub udp: BTreeSet<u16>,
+    pub udp6: BTreeSet<u16>,
+}
+
+/// The inodes of the sockets a process holds open.
+fn socket_inodes(pid: u32) -> Result<HashSet<u64>, String> {
+    let fds = fs::read_dir(format!("/proc/{}/fd", pid))
+        .map_err(|e| format!("Cannot read the sockets of process {}: {}", pid, e))?;
+    Ok(fds
+        .filter_map(|fd| fs::read_link(fd.ok()?.path()).ok())
+        .filter_map(|target| {
+            target
+                .to_str()?
+                .strip_prefix("socket:[")?
+                .strip_suffix(']')?
+                .parse()
+                .ok()
+        })
+        .collect())
+}
+
+/// Decodes a `/proc/net` address, which is printed as 32 bit words in host byte order.
+fn parse_proc_address(hex: &str) -> Option<IpAddr> {
+    let words = (0..hex.len())
+        .step_by(8)
+        .map(|i| {
+            u32::from_str_radix(hex.get(i..i + 8)?, 16)
+                .ok()
+                .map(u32::to_ne_bytes)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let octets = words.concat();
+    match octets.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(octets).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(octets).ok()?)),
+        _ => None,
+    }
+}
+
+/// The local addresses and ports of the sockets in a `/proc/net/{tcp,tcp6,udp,udp6}` table owned
+/// by `inodes`. Only listening TCP sockets are kept, and sockets bound to loopback addresses are
+/// skipped.
+fn parse_proc_net(table: &str, inodes: &HashSet<u64>, tcp: bool) -> Vec<(IpAddr, u16)> {
+    const TCP_LISTEN: &str = "0A";
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            let (address, port) = columns.get(1)?.split_once(':')?;
+            let inode = columns.get(9)?.parse::<u64>().ok()?;
+            let listening = !tcp || *columns.get(3)? == TCP_LISTEN;
+            let address = parse_proc_address(address)?;
+            let loopback = scope(&address) == Scope::Loopback;
+            (listening && !loopback && inodes.contains(&inode))
+                .then(|| Some((address, u16::from_str_radix(port, 16).ok()?)))
+                .flatten()
+        })
+        .collect()
+}
+
+/// The IPv4 and IPv6 ports of the sockets of one protocol. Sockets bound to `[::]` also accept
+/// IPv4 connections unless they set `IPV6_V6ONLY`, which `/proc` does not show, so their ports
+/// count for both.
+fn ports_by_family(v4: &[(IpAddr, u16)], v6: &[(IpAddr, u16)]) -> (BTreeSet<u16>, BTreeSet<u16>) {
+    let dual_stack = v6
+        .iter()
+        .filter(|(address, _)| address.is_unspecified())
+        .map(|(_, port)| *port);
+    (
+        v4.iter().map(|(_, port)| *port).chain(dual_stack).collect(),
+        v6.iter().map(|(_, port)| *port).collect(),
+    )
+}
+
+/// Reads the ports process `pid` has bound from `/proc`.
+pub fn listening_ports(pid: u32) -> Result<ListeningPorts, String> {
+    let inodes = socket_inodes(pid)?;
+    let table = |name: &str, tcp: bool| {
+        fs::read_to_string(format!("/proc/{}/net/{}", pid, name))
+            .map(|table| parse_proc_net(&table, &inodes, tcp))
+            .map_err(|e| format!("Cannot read /proc/{}/net/{}: {}", pid, name, e))
+    };
+    let (tcp, tcp6) = ports_by_family(&table("tcp", true)?, &table("tcp6", true)?);
+    let (udp, udp6) = ports_by_family(&table("udp", false)?, &table("udp6", false)?);
+    Ok(ListeningPorts {
+        tcp,
+        tcp6,
+        udp,
+        udp6,
+    })
+}
+
+/// The ports of one IP version in a record.
+struct Ports {
+    tcp: Option<u16>,
+    udp: Option<u16>,
+    quic: Option<u16>,
+}
+
+/// Splits the ports of one IP version into the tcp, udp (discovery) and quic ports. Several TCP
+/// ports are narrowed down to the one also bound for UDP, as clients share the libp2p and
+/// discovery port. Of two UDP ports, the one shared with TCP (or else the lower) is discovery and
+/// the other QUIC.
+fn assign(tcp: &BTreeSet<u16>, udp: &BTreeSet<u16>) -> Result<Ports, String> {
+    let tcp_port = match tcp.len() {
+        0 | 1 => tcp.first().copied(),
+        _ => {
+            let shared = tcp.intersection(udp).collect::<Vec<_>>();
+            match shared[..] {
+                [port] => Some(*port),
+                _ => {
+                    return Err(format!(
+                        "The process listens on several TCP ports {:?}, set --tcp-port",
+                        tcp
+                    ))
+                }
+            }
+        }
+    };
+    let (udp_port, quic_port) = match udp.iter().copied().collect::<Vec<_>>()[..] {
+        [] => (None, None),
+        [udp] => (Some(udp), None),
+        [low, high] if tcp_port == Some(high) => (Some(high), Some(low)),
+        [low, high] => (Some(low), Some(high)),
+        _ => {
+            return Err(format!(
+                "The process has bound several UDP ports {:?}, set --udp-port and --quic-port",
+                udp
+            ))
+        }
+    };
+    Ok(Ports {
+        tcp: tcp_port,
+        udp: udp_port,
+        quic: quic_port,
+    })
+}
+
+/// Sets the ports of `spec` that are not set yet from the ports a process listens on. Only the
+/// IP versions the record has an address of are filled, IPv4 if it has none.
+pub fn fill_ports(spec: &mut EnrSpec, ports: &ListeningPorts) -> Result<(), String> {
+    let (mut listens_v4, mut listens_v6) = (false, false);
+    for listen_addr in &spec.listen_addrs {
+        let multiaddr = listen_addr
+            .parse::<Multiaddr>()
+            .map_err(|_| "Invalid listen multiaddr")?;
+        for protocol in multiaddr.iter() {
+            match protocol {
+                Protocol::Ip4(_) => listens_v4 = true,
+                Protocol::Ip6(_) => listens_v6 = true,
+                _ => {}
+            }
+        }
+    }
+    let fill_v6 = spec.ip6.is_some() || listens_v6;
+    let fill_v4 = spec.ip.is_some() || listens_v4 || !fill_v6;
+    if fill_v4 {
+        if ports.tcp.is_empty() && ports.udp.is_empty() {
+            return Err("The process is not listening on any non-loopback IPv4 port".into());
+        }
+        let v4 = assign(&ports.tcp, &ports.udp)?;
+        spec.tcp_port = spec.tcp_port.or(v4.tcp);
+        spec.udp_port = spec.udp_port.or(v4.udp);
+        spec.quic_port = spec.quic_port.or(v4.quic);
+    }
+    if fill_v6 {
+        if ports.tcp6.is_empty() && ports.udp6.is_empty() {
+            return Err("The process is not listening on any non-loopback IPv6 port".into());
+        }
+        let v6 = assign(&ports.tcp6, &ports.udp6)?;
+        spec.tcp6_port = spec.tcp6_port.or(v6.tcp);
+        spec.udp6_port = spec.udp6_port.or(v6.udp);
+        spec.quic6_port = spec.quic6_port.or(v6.quic);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_addresses_and_ports() {
+        for (ip, expected) in [
+            ("1.1.1.1", Scope::Global),
+            ("10.1.2.3", Scope::Private),
+            ("100.64.0.1", Scope::Private),
+            ("169.254.0.1", Scope::LinkLocal),
+            ("127.0.0.1", Scope::Loopback),
+            ("0.1.2.3", Scope::Reserved),
+            ("192.0.2.1", Scope::Reserved),
+            ("198.18.0.1", Scope::Reserved),
+            ("198.19.255.255", Scope::Reserved),
+            ("198.20.0.1", Scope::Global),
+            ("198.51.100.7", Scope::Reserved),
+            ("203.0.113.9", Scope::Reserved),
+            ("2a00:1450::1", Scope::Global),
+            ("2001:db8::1", Scope::Reserved),
+            ("fd00::1", Scope::Private),
+            ("fec0::1", Scope::Private),
+            ("fe80::1", Scope::LinkLocal),
+            ("::1", Scope::Loopback),
+        ] {
+            assert_eq!(scope(&ip.parse().unwrap()), expected, "{}", ip);
+        }
+
+        // A Lighthouse node: libp2p on a dual-stack [::]:9000 plus an IPv6 only port, discovery
+        // and QUIC on 0.0.0.0:9000 and 9001, the HTTP API on localhost and a socket of another
+        // process.
+        let inodes = HashSet::from([11, 12, 13, 14, 15]);
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:13BC 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 12 1 0 100 0 0 10 0
+   1: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 99 1 0 100 0 0 10 0";
+        let tcp6 = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:2328 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 11 1 0 100 0 0 10 0
+   1: B80D0120000000000000000001000000:232A 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 15 1 0 100 0 0 10 0";
+        let udp = "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  1: 00000000:2328 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 13 2 0 0
+  2: 00000000:2329 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 14 2 0 0";
+        let (tcp, tcp6) = ports_by_family(
+            &parse_proc_net(tcp, &inodes, true),
+            &parse_proc_net(tcp6, &inodes, true),
+        );
+        let (udp, udp6) = ports_by_family(&parse_proc_net(udp, &inodes, false), &[]);
+        let ports = ListeningPorts {
+            tcp,
+            tcp6,
+            udp,
+            udp6,
+        };
+        assert_eq!(ports.tcp, BTreeSet::from([9000]));
+        assert_eq!(ports.tcp6, BTreeSet::from([9000, 9002]));
+
+        // The IPv6 ports are ambiguous, but an IPv4 record does not need them.
+        let mut spec = EnrSpec {
+            quic_port: Some(9101),
+            ..Default::default()
+        };
+        fill_ports(&mut spec, &ports).unwrap();
+        assert_eq!(
+            (spec.tcp_port, spec.udp_port, spec.quic_port),
+            (Some(9000), Some(9000), Some(9101))
+        );
+        assert_eq!(spec.tcp6_port, None);
+        let mut spec = EnrSpec {
+            ip6: Some(Ipv6Addr::LOCALHOST),
+            ..Default::default()
+        };
+        assert!(fill_ports(&mut spec, &ports).is_err());
+        let mut spec = EnrSpec {
+            listen_addrs: vec!["/ip6/::1/tcp/9000".to_string()],
+            ..Default::default()
+        };
+        assert!(fill_ports(&mut spec, &ports).is_err());
+
+        assert_eq!(
+            parse_proc_address("00000000000000000000000001000000"),
+            Some("::1".parse().unwrap())
+        );
+    }
+}
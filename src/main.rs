@@ -1,61 +1,11 @@
-//! # enr-cli
-//!
-//! This is currently a simple tool to read base64 encoded ENRs. More features may be added in
-//! the future.
-//!
-//! ## Install
-//!
-//! This can be installed via cargo:
-//!
-//! ```bash
-//! $ cargo install enr-cli
-//! ```
-//!
-//! ## Usage
-//!
-//! ```bash
-//! Sigma Prime <contact@sigmaprime.io>
-//! Simple CLI for reading and building ENRs.
-//!
-//! USAGE:
-//!     enr-cli [SUBCOMMAND]
-//!
-//! OPTIONS:
-//!     -h, --help       Print help information
-//!     -V, --version    Print version information
-//!
-//! SUBCOMMANDS:
-//!     build    Builds an ENR
-//!     help     Print this message or the help of the given subcommand(s)
-//!     read     Reads an ENR
-//!
-//!
-//! ## Example
-//!
-//! ```bash
-//! $ enr-cli read enr:-Ku4QJsxkOibTc9FXfBWYmcdMAGwH4bnOOFb4BlTHfMdx_f0WN-u4IUqZcQVP9iuEyoxipFs7-Qd_rH_0HfyOQitc7IBh2F0dG5ldHOIAAAAAAAAAACEZXRoMpD1pf1CAAAAAP__________gmlkgnY0gmlwhLAJM9iJc2VjcDI1NmsxoQL2RyM26TKZzqnUsyycHQB4jnyg6Wi79rwLXtaZXty06YN1ZHCCW8w
-//! ENR Read:
-//! Sequence No:1
-//! NodeId:0x3ab5..1447
-//! Libp2p PeerId:16Uiu2HAmC13Brucnz5qR8caKi8qKK6766PFoxsF5MzK2RvbTyBRr
-//! IP:176.9.51.216
-//! UDP Port:23500
-//! Known multiaddrs:
-//! /ip4/176.9.51.216/udp/23500/p2p/16Uiu2HAmC13Brucnz5qR8caKi8qKK6766PFoxsF5MzK2RvbTyBRr
-//! ```
-
-use clap::{Arg, Command};
-use enr::{CombinedKey, Enr as EnrRaw};
-mod enr_ext;
-pub mod eth2_ext;
-use ssz::Encode;
-
-use enr_ext::EnrExt;
-use eth2_ext::Eth2Enr;
-
-mod build;
-
-pub type Enr = EnrRaw<CombinedKey>;
+//! The `enr-cli` command line interface. See the library documentation for details.
+
+use clap::{Arg, ArgGroup, Command};
+use enr_cli::{
+    build, convert, encoding, enode, field, fleet, grind, inspect_dir, key_format, keys, message,
+    offline, output, rotate, scan, seq, testnet, update,
+};
+use std::io::Write;
 
 fn main() {
     // Parse the CLI parameters.
@@ -65,68 +15,608 @@ fn main() {
         .about("Simple CLI for reading and building ENRs.")
         .subcommand(read())
         .subcommand(build())
+        .subcommand(scan())
+        .subcommand(convert())
+        .subcommand(update())
+        .subcommand(rotate())
+        .subcommand(key())
+        .subcommand(inspect_dir())
+        .subcommand(attach_signature())
+        .subcommand(sign_message())
+        .subcommand(verify_message())
+        .subcommand(testnet())
+        .subcommand(fleet())
         .get_matches();
 
     // Handle the read logic
     if let Some(read_matches) = matches.subcommand_matches("read") {
-        let enr_base64 = read_matches
-            .get_one::<String>("enr")
-            .expect("Must supply an ENR");
-        let enr = enr_base64.parse::<Enr>().unwrap();
-        print_enr(enr);
+        if let Err(e) = read_record(read_matches) {
+            println!("Failed to read: {}", e);
+        }
     } else if let Some(build_matches) = matches.subcommand_matches("build") {
         // Handle the build ENR logic
         if let Err(e) = build::build(build_matches) {
             println!("Failed to build: {}", e);
         }
+    } else if let Some(scan_matches) = matches.subcommand_matches("scan") {
+        if let Err(e) = scan::scan(scan_matches) {
+            println!("Failed to scan: {}", e);
+        }
+    } else if let Some(convert_matches) = matches.subcommand_matches("convert") {
+        if let Err(e) = convert::convert(convert_matches) {
+            println!("Failed to convert: {}", e);
+        }
+    } else if let Some(update_matches) = matches.subcommand_matches("update") {
+        if let Err(e) = update::update(update_matches) {
+            println!("Failed to update: {}", e);
+        }
+    } else if let Some(rotate_matches) = matches.subcommand_matches("rotate") {
+        if let Err(e) = rotate::rotate(rotate_matches) {
+            println!("Failed to rotate: {}", e);
+        }
+    } else if let Some(key_matches) = matches.subcommand_matches("key") {
+        if let Some(new_matches) = key_matches.subcommand_matches("new") {
+            if let Err(e) = key_format::new(new_matches) {
+                println!("Failed to generate key: {}", e);
+            }
+        } else if let Some(derive_matches) = key_matches.subcommand_matches("derive") {
+            if let Err(e) = key_format::derive(derive_matches) {
+                println!("Failed to derive key: {}", e);
+            }
+        } else if let Some(grind_matches) = key_matches.subcommand_matches("grind") {
+            if let Err(e) = grind::grind_key(grind_matches) {
+                println!("Failed to grind key: {}", e);
+            }
+        } else if let Some(import_matches) = key_matches.subcommand_matches("import") {
+            if let Err(e) = key_format::import(import_matches) {
+                println!("Failed to import key: {}", e);
+            }
+        } else if let Some(export_matches) = key_matches.subcommand_matches("export") {
+            if let Err(e) = key_format::export(export_matches) {
+                println!("Failed to export key: {}", e);
+            }
+        } else if let Some(convert_matches) = key_matches.subcommand_matches("convert") {
+            if let Err(e) = key_format::convert(convert_matches) {
+                println!("Failed to convert key: {}", e);
+            }
+        }
+    } else if let Some(inspect_matches) = matches.subcommand_matches("inspect-dir") {
+        if let Err(e) = inspect_dir::inspect_dir(inspect_matches) {
+            println!("Failed to inspect directory: {}", e);
+        }
+    } else if let Some(attach_matches) = matches.subcommand_matches("attach-signature") {
+        if let Err(e) = offline::attach_signature(attach_matches) {
+            println!("Failed to attach signature: {}", e);
+        }
+    } else if let Some(sign_matches) = matches.subcommand_matches("sign-message") {
+        if let Err(e) = message::sign_message(sign_matches) {
+            println!("Failed to sign message: {}", e);
+        }
+    } else if let Some(verify_matches) = matches.subcommand_matches("verify-message") {
+        if let Err(e) = message::verify_message(verify_matches) {
+            println!("Failed to verify message: {}", e);
+            // Scripts check the exit code to tell whether a node owns the key.
+            std::process::exit(1);
+        }
+    } else if let Some(testnet_matches) = matches.subcommand_matches("testnet") {
+        if let Some(generate_matches) = testnet_matches.subcommand_matches("generate") {
+            if let Err(e) = testnet::generate(generate_matches) {
+                println!("Failed to generate testnet: {}", e);
+            }
+        }
+    } else if let Some(fleet_matches) = matches.subcommand_matches("fleet") {
+        if let Some(rebuild_matches) = fleet_matches.subcommand_matches("rebuild") {
+            if let Err(e) = fleet::rebuild(rebuild_matches) {
+                println!("Failed to rebuild fleet: {}", e);
+            }
+        }
+    }
+}
+
+/// Reads an ENR or an enode URL and prints it.
+fn read_record(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let record: Vec<u8> = if let Some(record) = matches.get_one::<String>("enr") {
+        record.clone().into_bytes()
+    } else {
+        let file = matches
+            .get_one::<String>("file")
+            .expect("Must supply an ENR or a file");
+        std::fs::read(file).map_err(|_| "Cannot read ENR file")?
+    };
+    let format = output::OutputFormat::from_matches(matches)?;
+
+    if record.starts_with(b"enode://") {
+        let enode = std::str::from_utf8(&record)
+            .map_err(|_| "Invalid enode")?
+            .parse::<enode::Enode>()?;
+        output::print_enode_as(&enode, format);
+        return Ok(());
+    }
+
+    let input_format = matches
+        .get_one::<String>("input-format")
+        .map(|f| f.parse::<encoding::RecordEncoding>())
+        .transpose()?;
+    let enr = encoding::decode_record(&record, input_format)?;
+
+    if let Some(reencode) = matches.get_one::<String>("reencode") {
+        let encoded = encoding::encode_record(&enr, reencode.parse()?);
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(&encoded)
+            .map_err(|_| "Cannot write to stdout")?;
+        if reencode != "rlp" {
+            println!();
+        }
+    } else {
+        output::print_enr_as(enr, format);
     }
+    Ok(())
 }
 
 fn read() -> Command {
-    Command::new("read").about("Reads an ENR").arg(
-        Arg::new("enr")
-            .value_name("BASE64-ENR")
-            .allow_hyphen_values(true)
-            .required(true)
-            .help("Reads a base64 ENR and prints common parameters."),
-    )
+    Command::new("read")
+        .about("Reads an ENR")
+        .arg(
+            Arg::new("enr")
+                .value_name("ENR")
+                .allow_hyphen_values(true)
+                .help("Reads an ENR or an enode:// URL and prints common parameters. The ENR may be base64 (with or without the enr: prefix) or hex encoded RLP."),
+        )
+        .arg(
+            Arg::new("file")
+                .long("file")
+                .short('F')
+                .help("Read the ENR from a file instead, e.g. ~/.lighthouse/mainnet/beacon/network/enr.dat. Binary RLP files are supported."),
+        )
+        .group(
+            ArgGroup::new("record")
+                .args(["enr", "file"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("input-format")
+                .long("input-format")
+                .value_parser(encoding::RecordEncoding::VARIANTS)
+                .help("The encoding of the ENR. Detected automatically if not given."),
+        )
+        .arg(
+            Arg::new("reencode")
+                .long("reencode")
+                .value_parser(encoding::RecordEncoding::VARIANTS)
+                .conflicts_with("format")
+                .help("Print only the record in the given encoding. `rlp` writes raw bytes."),
+        )
+        .arg(format_arg())
 }
 
-fn build() -> Command {
-    Command::new("build")
-        .about("Builds an ENR")
+fn scan() -> Command {
+    Command::new("scan")
+        .about("Extracts ENRs, enode URLs and /p2p/ multiaddrs from arbitrary text such as logs")
+        .arg(
+            Arg::new("input")
+                .value_name("FILE")
+                .num_args(0..)
+                .help("Files to search. Reads stdin if no file (or -) is given."),
+        )
+        .arg(format_arg())
+}
+
+fn inspect_dir() -> Command {
+    Command::new("inspect-dir")
+        .about("Decodes the node key and ENR stored in a client's network directory and checks that they match")
+        .arg(
+            Arg::new("dir")
+                .value_name("PATH")
+                .required(true)
+                .help("The directory to inspect, e.g. ~/.lighthouse/mainnet/beacon/network"),
+        )
+        .arg(
+            Arg::new("client")
+                .long("client")
+                .value_parser(key_format::ClientFormat::VARIANTS)
+                .help("Only look for the files of this client. All known layouts are tried by default"),
+        )
+        .arg(password_file_arg())
+}
+
+fn update() -> Command {
+    Command::new("update")
+        .about("Modifies an existing ENR and re-signs it with the sequence number incremented")
+        .arg(
+            Arg::new("enr")
+                .value_name("BASE64-ENR")
+                .allow_hyphen_values(true)
+                .required(true)
+                .help("The ENR to update"),
+        )
+        .args(key_args())
+        .arg(signer_url_arg())
         .arg(
-            Arg::new("private-key")
-                .short('k')
-                .long("private-key")
+            Arg::new("seq")
+                .long("seq-no")
+                .short('s')
+                .help("Set the sequence number instead of incrementing it"),
+        )
+        .args(seq_args())
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .action(clap::ArgAction::Append)
+                .help("Set a key, e.g. udp=9001 or ip=1.2.3.4. Keys other than ip, ip6, the port keys and eth2 take 0x-prefixed hex bytes. Can be given multiple times."),
+        )
+        .arg(
+            Arg::new("unset")
+                .long("unset")
+                .value_name("KEY")
+                .action(clap::ArgAction::Append)
+                .help("Remove a key from the record. Can be given multiple times."),
+        )
+}
+
+fn rotate() -> Command {
+    Command::new("rotate")
+        .about("Moves every non-identity key of an ENR to a record signed by a new node key")
+        .arg(
+            Arg::new("enr")
+                .value_name("BASE64-ENR")
                 .allow_hyphen_values(true)
-                .help("A hex encoded private key to use for signing. If this or --key-file is not specified a random one will be generated")
+                .required(true)
+                .help("The ENR to rotate"),
+        )
+        .args(key_args())
+        .arg(
+            Arg::new("new-key")
+                .long("new-key")
+                .value_name("FILE")
+                .help("A file holding the raw bytes of the new key. A new key is generated by default"),
+        )
+        .arg(
+            Arg::new("new-key-type")
+                .long("new-key-type")
+                .value_parser(keys::KeyType::VARIANTS)
+                .help("The type of the new key. Defaults to the type of the old key"),
+        )
+        .arg(
+            Arg::new("new-key-out")
+                .long("new-key-out")
+                .value_name("FILE")
+                .help("Write the new key in the --format client key format to a new FILE readable only by the owner"),
+        )
+        .arg(
+            Arg::new("old-key-out")
+                .long("old-key-out")
+                .value_name("FILE")
+                .help("Write the old key, given with --private-key, --key-file or --keystore, in the --format client key format"),
+        )
+        .arg(
+            client_format_arg()
+                .required(false)
+                .help("The client key format of --new-key-out and --old-key-out. Defaults to lighthouse (raw key bytes)"),
+        )
+        .arg(
+            Arg::new("enr-out")
+                .long("enr-out")
+                .value_name("FILE")
+                .help("Write the base64 record to FILE, as in a Lighthouse enr.dat"),
+        )
+}
+
+/// The flags that supply the key used to sign a record.
+fn key_args() -> [Arg; 7] {
+    [
+        Arg::new("private-key")
+            .short('k')
+            .long("private-key")
+            .allow_hyphen_values(true)
+            .help("A hex encoded private key to use for signing. `build` generates a random one if no key is specified. Prefer --private-key-env or --private-key-stdin, which keep the key out of process listings and shell history"),
+        Arg::new("private-key-env")
+            .long("private-key-env")
+            .value_name("VAR")
+            .conflicts_with_all(["private-key", "key-file", "keystore"])
+            .help("Read the hex encoded private key from the environment variable VAR"),
+        Arg::new("private-key-stdin")
+            .long("private-key-stdin")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with_all(["private-key", "private-key-env", "key-file", "keystore"])
+            .help("Read the hex encoded private key from stdin"),
+        Arg::new("key-file")
+            .short('j')
+            .long("key-file")
+            .allow_hyphen_values(true)
+            .help("Path to a key file that stores raw bytes of an ENR key. Example for lighthouse is in ~/.lighthouse/mainnet/beacon/network/key.dat."),
+        Arg::new("key-type")
+            .long("key-type")
+            .value_parser(keys::KeyType::VARIANTS)
+            .help("The type of the imported or generated key. Imported keys are tried as secp256k1 then ed25519 if this is not specified, `build` generates secp256k1"),
+        Arg::new("keystore")
+            .long("keystore")
+            .value_name("FILE")
+            .conflicts_with_all(["private-key", "key-file"])
+            .requires("password-file")
+            .help("Path to an encrypted keystore, as written by `key new --encrypt`"),
+        password_file_arg(),
+    ]
+}
+
+fn signer_url_arg() -> Arg {
+    Arg::new("signer-url")
+        .long("signer-url")
+        .value_name("URL")
+        .conflicts_with_all(["private-key", "private-key-env", "private-key-stdin", "key-file", "keystore"])
+        .help("Sign with a remote signing service instead of a local key. The service answers GET <URL>/public_key and POST <URL>/sign")
+}
+
+fn print_secret_arg() -> Arg {
+    Arg::new("print-secret")
+        .long("print-secret")
+        .action(clap::ArgAction::SetTrue)
+        .help("Print the private key. Secrets are not printed by default")
+}
+
+fn password_file_arg() -> Arg {
+    Arg::new("password-file")
+        .long("password-file")
+        .value_name("FILE")
+        .help("Path to a file holding the password of an encrypted key. A trailing newline is ignored")
+}
+
+fn client_format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .short('o')
+        .required(true)
+        .value_parser(key_format::ClientFormat::VARIANTS)
+        .help("The client whose key file format to use")
+}
+
+/// The flags that choose how a new key is stored.
+fn new_key_args() -> [Arg; 6] {
+    [
+        Arg::new("key-type")
+            .long("key-type")
+            .value_parser(keys::KeyType::VARIANTS)
+            .help("The type of key to generate. Defaults to secp256k1"),
+        Arg::new("encrypt")
+            .long("encrypt")
+            .action(clap::ArgAction::SetTrue)
+            .requires("password-file")
+            .help("Write the key as an EIP-2335 style keystore encrypted with the password in --password-file"),
+        Arg::new("kdf")
+            .long("kdf")
+            .value_parser(["scrypt", "pbkdf2"])
+            .requires("encrypt")
+            .help("The key derivation function of the keystore. Defaults to scrypt"),
+        password_file_arg(),
+        print_secret_arg(),
+        Arg::new("out")
+            .long("out")
+            .value_name("FILE")
+            .help("Write the raw key, or the keystore with --encrypt, to a new FILE readable only by the owner"),
+    ]
+}
+
+/// The flags that choose the sequence number of a new version of a record.
+fn seq_args() -> [Arg; 2] {
+    [
+        Arg::new("seq-policy")
+            .long("seq-policy")
+            .value_parser(seq::SeqPolicy::VARIANTS)
+            .help("How to choose the sequence number: `increment` the previous record's or --seq-state's, use the unix `timestamp` in seconds, or the `explicit` --seq-no. Defaults to increment when a previous sequence number is known, unless --seq-no is given"),
+        Arg::new("seq-state")
+            .long("seq-state")
+            .value_name("FILE")
+            .help("A file keeping the last sequence number used, read by the increment policy and updated with each new record"),
+    ]
+}
+
+/// The flags that derive a key from a seed.
+fn seed_args() -> [Arg; 2] {
+    [
+        Arg::new("seed")
+            .long("seed")
+            .value_name("HEX|MNEMONIC")
+            .help(
+            "Derive the key from this seed, given as at least 16 hex bytes or a BIP-39 mnemonic",
+        ),
+        Arg::new("index")
+            .long("index")
+            .value_name("N")
+            .requires("seed")
+            .help("The index of the key derived from --seed. Defaults to 0"),
+    ]
+}
+
+fn key() -> Command {
+    Command::new("key")
+        .about("Manages node keys")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("new")
+                .about("Generates a new node key")
+                .args(new_key_args()),
+        )
+        .subcommand(
+            Command::new("derive")
+                .about("Derives a node key deterministically from a seed, for reproducible test networks")
+                .args(seed_args())
+                .args(new_key_args())
+                .mut_arg("seed", |arg| arg.required(true)),
+        )
+        .subcommand(
+            Command::new("grind")
+                .about("Searches for a key whose node id has a prefix or lies at a log2 distance from a node id")
+                .arg(
+                    Arg::new("prefix")
+                        .long("prefix")
+                        .value_name("HEX")
+                        .help("The hex digits the node id must start with, e.g. 0xabcd"),
+                )
+                .arg(
+                    Arg::new("near")
+                        .long("near")
+                        .value_name("NODE-ID")
+                        .requires("distance")
+                        .help("The hex node id to measure the distance from"),
+                )
+                .arg(
+                    Arg::new("distance")
+                        .long("distance")
+                        .value_name("N")
+                        .requires("near")
+                        .help("The log2 distance (1 to 256) from --near, as in discv5 FINDNODE. Each step below 256 doubles the search time"),
+                )
+                .group(
+                    ArgGroup::new("target")
+                        .args(["prefix", "near"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .value_name("N")
+                        .help("The number of search threads. Defaults to the number of cores"),
+                )
+                .args(new_key_args()),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Reads a node key file written by a client")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("The key file, e.g. key.dat, network-keys or peer-id.json"),
+                )
+                .arg(client_format_arg())
+                .arg(password_file_arg())
+                .arg(print_secret_arg()),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Writes a node key in a client's key file format")
+                .args(key_args())
+                .arg(client_format_arg())
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Write the key file to a new FILE readable only by the owner instead of stdout"),
+                ),
         )
+        .subcommand(
+            Command::new("convert")
+                .about("Converts a private key between hex, libp2p protobuf, PEM and JWK encodings")
+                .arg(
+                    Arg::new("input")
+                        .value_name("FILE")
+                        .help("The key to convert. Reads stdin if this is not specified or `-`"),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .required(true)
+                        .value_parser(key_format::KeyEncoding::VARIANTS)
+                        .help("The encoding of the input key. `pem` reads both PKCS#8 and SEC1 PEMs"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .required(true)
+                        .value_parser(key_format::KeyEncoding::VARIANTS)
+                        .help("The encoding to write. `pem` writes PKCS#8"),
+                )
+                .arg(
+                    Arg::new("key-type")
+                        .long("key-type")
+                        .value_parser(keys::KeyType::VARIANTS)
+                        .help("The type of a hex input key. Tried as secp256k1 then ed25519 if this is not specified"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Write the key to a new FILE readable only by the owner instead of stdout"),
+                ),
+        )
+}
+
+fn convert() -> Command {
+    Command::new("convert")
+        .about("Converts between ENRs, enode URLs, peer ids, public keys and /p2p/ multiaddrs")
         .arg(
-            Arg::new("key-file")
-                .short('j')
-                .long("key-file")
+            Arg::new("input")
+                .value_name("IDENTITY")
+                .num_args(0..)
                 .allow_hyphen_values(true)
-                .help("Path to a key file that stores raw bytes of an ENR key. Example for lighthouse is in ~/.lighthouse/mainnet/beacon/network/key.dat.")
+                .help("An ENR, enode URL, peer id (base58 or CIDv1), hex secp256k1 public key or /p2p/ multiaddr. Reads one per line from stdin if none (or -) is given."),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('o')
+                .value_parser(["text", "json"])
+                .help("Output format"),
+        )
+}
+
+/// The `--format` flag shared by the commands that print records.
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .short('o')
+        .value_parser(output::OutputFormat::VARIANTS)
+        .help("Output format. `enr` prints only the base64 record (or the original enode/multiaddr where no record is known)")
+}
+
+fn build() -> Command {
+    Command::new("build")
+        .about("Builds an ENR")
+        .args(key_args())
+        .arg(
+            Arg::new("spec")
+                .long("spec")
+                .value_name("FILE")
+                .help("Read the record from a .toml, .yaml or .json spec file. Flags given on the command line override values in the file")
         )
         .arg(
             Arg::new("ip")
                 .long("ip")
                 .short('i')
-                .help("Set an IPv4 address")
+                .help("Set an IPv4 address. `auto` picks the first global address of the host, `iface:<name>` that of an interface, e.g. iface:eth0")
         )
         .arg(
             Arg::new("ip6")
                 .long("ip6")
                 .short('I')
-                .help("Set an IPv6 address")
+                .help("Set an IPv6 address. Takes `auto` and `iface:<name>` like --ip")
                 )
+        .arg(
+            Arg::new("allow-private-ip")
+                .long("allow-private-ip")
+                .action(clap::ArgAction::SetTrue)
+                .help("Let --ip and --ip6 `auto` or `iface:` pick a private, link-local or loopback address when there is no global one")
+        )
+        .arg(
+            Arg::new("ports-from-listening")
+                .long("ports-from-listening")
+                .value_name("PID")
+                .help("Set the ports that are not given from the sockets process PID has bound, read from /proc. Only the IP versions the record has an address for are filled, IPv4 if none. With two UDP ports, the one shared with TCP is discovery and the other QUIC")
+        )
         .arg(
             Arg::new("seq")
                 .long("seq-no")
                 .short('s')
                 .help("Set a sequence number")
         )
+        .args(seq_args())
+        .arg(
+            Arg::new("previous-enr")
+                .long("previous-enr")
+                .value_name("ENR|FILE")
+                .help("The record this one replaces, for --seq-policy. An existing --enr-out file is used otherwise")
+        )
         .arg(
             Arg::new("tcp-port")
                 .long("tcp-port")
@@ -161,60 +651,263 @@ fn build() -> Command {
                 .short('Q')
                 .help("Set an IPv6 QUIC port")
         )
+        .arg(
+            Arg::new("listen-addr")
+                .long("listen-addr")
+                .short('l')
+                .action(clap::ArgAction::Append)
+                .help("Set the IP and a tcp, udp or quic port from a multiaddr, e.g. /ip4/1.2.3.4/tcp/9000 or /ip6/::1/udp/9001/quic-v1. Can be given multiple times. A /p2p/ component must match the signing key")
+        )
         .arg(
             Arg::new("eth2")
                 .long("eth2")
                 .short('f')
                 .help("Set an eth2 fork field. Takes the raw SSZ bytes input")
         )
+        .arg(
+            Arg::new("field")
+                .long("field")
+                .value_name("NAME=TYPE:VALUE")
+                .action(clap::ArgAction::Append)
+                .help(format!("Set a custom key. TYPE is one of {}, e.g. --field custom=u64:10. bytes, ssz-hex and rlp-hex take hex values. Can be given multiple times.", field::FIELD_TYPES.join(", ")))
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(clap::ArgAction::SetTrue)
+                .help("Allow --field to set the reserved id, secp256k1 and ed25519 keys. Only an ed25519 key beside a secp256k1 signing key is kept, other reserved keys come from the signing key")
+        )
+        .arg(
+            Arg::new("key-out")
+                .long("key-out")
+                .value_name("FILE")
+                .help("Write the raw key bytes (a Lighthouse key.dat) to a new FILE readable only by the owner")
+        )
+        .arg(
+            Arg::new("enr-out")
+                .long("enr-out")
+                .value_name("FILE")
+                .help("Write the base64 record to FILE, as in a Lighthouse enr.dat")
+        )
+        .arg(print_secret_arg())
+        .arg(signer_url_arg().conflicts_with_all(["key-out", "print-secret"]))
+        .args(seed_args().map(|arg| arg.conflicts_with_all(["private-key", "private-key-env", "private-key-stdin", "key-file", "keystore", "signer-url", "unsigned"])))
+        .arg(
+            Arg::new("unsigned")
+                .long("unsigned")
+                .action(clap::ArgAction::SetTrue)
+                .requires("public-key")
+                .conflicts_with_all(["private-key", "private-key-env", "private-key-stdin", "key-file", "keystore", "signer-url", "key-out", "enr-out", "print-secret"])
+                .help("Build an unsigned template for a key held elsewhere instead of signing the record. Prints the content to sign, see `attach-signature`")
+        )
+        .arg(
+            Arg::new("public-key")
+                .long("public-key")
+                .value_name("HEX")
+                .requires("unsigned")
+                .help("The hex encoded public key that will sign an --unsigned record, ed25519 (32 bytes) or secp256k1")
+        )
+        .arg(
+            Arg::new("template-out")
+                .long("template-out")
+                .value_name("FILE")
+                .requires("unsigned")
+                .help("Write the --unsigned template to FILE instead of stdout")
+        )
 }
 
-pub fn print_enr(enr: Enr) {
-    println!("ENR Read:");
-    println!("Sequence No:{}", enr.seq());
-    println!("NodeId: {}", hex::encode(enr.node_id().raw()));
-    println!("EnodeId: {}", enr.enode_id());
-    println!("Libp2p PeerId: {}", enr.peer_id());
-    if let Some(ip) = enr.ip4() {
-        println!("ipv4:{:?}", ip);
-    }
-    if let Some(ip) = enr.ip6() {
-        println!("ipv6:{:?}", ip);
-    }
-    if let Some(tcp) = enr.tcp4() {
-        println!("v4_tcp:{}", tcp);
-    }
-    if let Some(tcp) = enr.tcp6() {
-        println!("v6_tcp:{}", tcp);
-    }
-    if let Some(udp) = enr.udp4() {
-        println!("v4_udp:{}", udp);
-    }
-    if let Some(udp) = enr.udp6() {
-        println!("v6_udp:{}", udp);
-    }
-    if let Some(udp) = enr.quic4() {
-        println!("v4_quic:{}", udp);
-    }
-    if let Some(udp) = enr.quic6() {
-        println!("v6_quic:{}", udp);
-    }
+fn sign_message() -> Command {
+    Command::new("sign-message")
+        .about("Signs a message with a node key, to prove control of its node id")
+        .args(key_args())
+        .arg(
+            Arg::new("message")
+                .value_name("MESSAGE")
+                .required(true)
+                .help("The message to sign"),
+        )
+}
 
-    if let Ok(enr_fork_id) = enr.eth2() {
-        println!(
-            "Eth2 Field:\n\tFork digest: {}\n\tNext fork version: {}\n\tNext fork epoch: {}\n\tSSZ Bytes: {}",
-            hex::encode(enr_fork_id.fork_digest),
-            hex::encode(enr_fork_id.next_fork_version),
-            enr_fork_id.next_fork_epoch,
-            hex::encode(enr_fork_id.as_ssz_bytes())
-        );
-    }
+fn verify_message() -> Command {
+    Command::new("verify-message")
+        .about("Verifies a message signed by `sign-message`")
+        .arg(
+            Arg::new("signer")
+                .value_name("ENR|PUBKEY")
+                .required(true)
+                .help("The ENR, peer id or hex encoded public key of the signer"),
+        )
+        .arg(
+            Arg::new("message")
+                .value_name("MESSAGE")
+                .required(true)
+                .help("The signed message"),
+        )
+        .arg(
+            Arg::new("signature")
+                .value_name("SIG-HEX")
+                .required(true)
+                .help("The hex encoded signature"),
+        )
+}
 
-    let multiaddrs = enr.multiaddr();
-    if !multiaddrs.is_empty() {
-        println!("Known multiaddrs:");
-        for multiaddr in multiaddrs {
-            println!("{}", multiaddr);
-        }
-    }
+fn testnet() -> Command {
+    Command::new("testnet")
+        .about("Bootstraps local test networks")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("generate")
+                .about("Writes node keys, ENRs, bootstrap files and client flags for N nodes")
+                .arg(
+                    Arg::new("nodes")
+                        .long("nodes")
+                        .value_name("N")
+                        .required(true)
+                        .help("The number of nodes"),
+                )
+                .arg(
+                    Arg::new("ip-base")
+                        .long("ip-base")
+                        .value_name("IP")
+                        .default_value("127.0.0.1")
+                        .help("The IPv4 address of the first node. Node i gets this address plus i"),
+                )
+                .arg(
+                    Arg::new("port-base")
+                        .long("port-base")
+                        .value_name("PORT")
+                        .default_value("9000")
+                        .help("The tcp and udp port of the first node. Node i gets this port plus i"),
+                )
+                .arg(
+                    Arg::new("network-config")
+                        .long("network-config")
+                        .value_name("FILE")
+                        .help("A consensus network config.yaml. The eth2 fork id at genesis is derived from it and the genesis.ssz next to it or --genesis-validators-root"),
+                )
+                .arg(
+                    Arg::new("genesis-validators-root")
+                        .long("genesis-validators-root")
+                        .value_name("HEX")
+                        .requires("network-config")
+                        .help("The genesis validators root used for the fork digest"),
+                )
+                .arg(
+                    Arg::new("eth2")
+                        .long("eth2")
+                        .value_name("HEX")
+                        .conflicts_with("network-config")
+                        .help("The ssz encoded eth2 field of every node, instead of deriving it from --network-config"),
+                )
+                .arg(
+                    Arg::new("client")
+                        .long("client")
+                        .default_value("lighthouse")
+                        .value_parser(testnet::CLIENTS)
+                        .help("The client to write key files and flags for"),
+                )
+                .arg(
+                    Arg::new("key-type")
+                        .long("key-type")
+                        .value_parser(keys::KeyType::VARIANTS)
+                        .help("The type of the node keys. Defaults to secp256k1"),
+                )
+                .arg(seed_args()[0].clone().help("Derive node i's key from this seed at index i, see `key derive`. Keys are random otherwise"))
+                .arg(
+                    Arg::new("out-dir")
+                        .long("out-dir")
+                        .value_name("DIR")
+                        .default_value("testnet")
+                        .help("The directory to write to. Existing key files are never overwritten"),
+                ),
+        )
+}
+
+fn fleet() -> Command {
+    Command::new("fleet")
+        .about("Manages the records of many nodes at once")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("rebuild")
+                .about("Re-signs the record of every key in a directory from a shared spec, bumping the sequence number of records that change")
+                .arg(
+                    Arg::new("keys-dir")
+                        .long("keys-dir")
+                        .value_name("DIR")
+                        .required(true)
+                        .help("The directory of node key files. Each node is named after its key file, without the extension"),
+                )
+                .arg(
+                    Arg::new("key-extension")
+                        .long("key-extension")
+                        .value_name("EXT")
+                        .default_value("key")
+                        .help("The extension of the key files in --keys-dir. Other files are skipped"),
+                )
+                .arg(
+                    Arg::new("template")
+                        .long("template")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("A .toml, .yaml or .json spec file shared by all nodes, see `build --spec`"),
+                )
+                .arg(
+                    Arg::new("ip-map")
+                        .long("ip-map")
+                        .value_name("FILE")
+                        .help("A CSV of `name,ip[,ip]` lines giving the IPv4 and/or IPv6 address of each node, overriding the template"),
+                )
+                .arg(
+                    Arg::new("enr-dir")
+                        .long("enr-dir")
+                        .value_name("DIR")
+                        .help("The directory of the `<name>.enr` records, read for the previous sequence number and written with the new records. Defaults to --keys-dir"),
+                )
+                .arg(
+                    client_format_arg()
+                        .required(false)
+                        .help("The client format of the key files. Raw key bytes are read by default"),
+                )
+                .arg(
+                    Arg::new("key-type")
+                        .long("key-type")
+                        .value_parser(keys::KeyType::VARIANTS)
+                        .help("The type of raw key files. Required for ed25519 keys, unless the template sets key_type"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Show the changes each record would get without writing anything"),
+                ),
+        )
+}
+
+fn attach_signature() -> Command {
+    Command::new("attach-signature")
+        .about("Adds a detached signature to a template from `build --unsigned`, producing the signed ENR")
+        .arg(
+            Arg::new("template")
+                .value_name("TEMPLATE")
+                .required(true)
+                .help("The template file written by `build --unsigned`"),
+        )
+        .arg(
+            Arg::new("signature")
+                .value_name("SIG-HEX")
+                .required(true)
+                .help("The hex encoded signature. secp256k1 signatures are r || s over the signing hash, a trailing recovery id is ignored"),
+        )
+        .arg(
+            Arg::new("enr-out")
+                .long("enr-out")
+                .value_name("FILE")
+                .help("Write the base64 record to FILE, as in a Lighthouse enr.dat"),
+        )
+        .arg(
+            Arg::new("seq-state")
+                .long("seq-state")
+                .value_name("FILE")
+                .help("The --seq-state file of `build --unsigned`, updated with the sequence number of the signed record"),
+        )
 }
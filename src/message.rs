@@ -0,0 +1,150 @@
+//! Signs and verifies arbitrary messages with a node key, to prove control of a node id.
+use crate::convert;
+use crate::enr_ext::CombinedKeyPublicExt;
+use crate::keys::{self, KeyType};
+use crate::offline;
+use enr::{CombinedKey, CombinedPublicKey, EnrKey, EnrPublicKey, NodeId};
+use serde_json::json;
+
+/// Prepended to messages before signing, so that a signed message can never be mistaken for a
+/// signed record, whose content starts with an RLP list header.
+const MESSAGE_PREFIX: &str = "\x19ENR Signed Message:\n";
+
+/// The bytes that are signed for `message`: the prefix, the message length and the message.
+fn signing_payload(message: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}{}", MESSAGE_PREFIX, message.len()).into_bytes();
+    payload.extend_from_slice(message);
+    payload
+}
+
+/// Signs a message with the `v4` identity scheme of the key: secp256k1 keys sign the keccak256 hash
+/// of the payload, ed25519 keys sign the payload itself.
+pub fn sign(key: &CombinedKey, message: &[u8]) -> Result<Vec<u8>, &'static str> {
+    key.sign_v4(&signing_payload(message))
+        .map_err(|_| "Failed to sign the message")
+}
+
+/// Checks a signature made by `sign`.
+pub fn verify(public_key: &CombinedPublicKey, message: &[u8], signature: &[u8]) -> bool {
+    public_key.verify_v4(&signing_payload(message), signature)
+}
+
+/// Parses the signer of a message: an ENR, or anything else `convert` accepts. Hex public keys may
+/// also be 32 byte ed25519 keys.
+fn parse_signer(input: &str) -> Result<CombinedPublicKey, &'static str> {
+    let input = input.trim();
+    let is_hex = input
+        .trim_start_matches("0x")
+        .chars()
+        .all(|c| c.is_ascii_hexdigit());
+    if is_hex {
+        return offline::parse_public_key(input);
+    }
+    let identity = convert::parse_identity(input)?;
+    if identity.enr.as_ref().is_some_and(|enr| !enr.verify()) {
+        return Err("The ENR has an invalid signature");
+    }
+    Ok(identity.public_key)
+}
+
+pub fn sign_message(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let key = keys::load_key(matches)?.ok_or("A key is required to sign a message")?;
+    let message = matches
+        .get_one::<String>("message")
+        .expect("Must supply a message");
+    let signature = sign(&key, message.as_bytes())?;
+
+    let public_key = key.public();
+    let signed = json!({
+        "message": message,
+        "signature": format!("0x{}", hex::encode(signature)),
+        "key_type": KeyType::of(&public_key).to_string(),
+        "public_key": format!("0x{}", hex::encode(public_key.encode())),
+        "node_id": format!("0x{}", hex::encode(NodeId::from(public_key.clone()).raw())),
+        "peer_id": public_key.as_peer_id().to_string(),
+    });
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&signed).expect("valid json")
+    );
+    Ok(())
+}
+
+pub fn verify_message(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let public_key = parse_signer(
+        matches
+            .get_one::<String>("signer")
+            .expect("Must supply a signer"),
+    )?;
+    let message = matches
+        .get_one::<String>("message")
+        .expect("Must supply a message");
+    let signature = hex::decode(
+        matches
+            .get_one::<String>("signature")
+            .expect("Must supply a signature")
+            .trim()
+            .trim_start_matches("0x"),
+    )
+    .map_err(|_| "Invalid signature hex")?;
+
+    if !verify(&public_key, message.as_bytes(), &signature) {
+        return Err("Invalid signature");
+    }
+    println!("Valid signature");
+    println!("Key Type: {}", KeyType::of(&public_key));
+    println!(
+        "NodeId: 0x{}",
+        hex::encode(NodeId::from(public_key.clone()).raw())
+    );
+    println!("Libp2p PeerId: {}", public_key.as_peer_id());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enr_ext::EnrExt;
+
+    #[test]
+    fn test_messages_verify_against_the_record() {
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let enr = enr::Builder::default().build(&key).unwrap();
+            let signature = sign(&key, b"peering with node 42").unwrap();
+
+            for signer in [enr.to_base64(), enr.peer_id().to_string()] {
+                let public_key = parse_signer(&signer).unwrap();
+                assert!(verify(&public_key, b"peering with node 42", &signature));
+                assert!(!verify(&public_key, b"peering with node 43", &signature));
+            }
+            // A message signature is not a record signature over the same bytes.
+            assert!(!key.public().verify_v4(b"peering with node 42", &signature));
+        }
+    }
+
+    #[test]
+    fn test_verify_message_rejects_other_keys() {
+        let command = clap::Command::new("verify-message")
+            .arg(clap::Arg::new("signer"))
+            .arg(clap::Arg::new("message"))
+            .arg(clap::Arg::new("signature"));
+        let key = CombinedKey::generate_secp256k1();
+        let signature = format!("0x{}", hex::encode(sign(&key, b"hello").unwrap()));
+        let verify_with = |signer: &CombinedKey| {
+            let signer = enr::Builder::default().build(signer).unwrap().to_base64();
+            let matches = command
+                .clone()
+                .try_get_matches_from(["verify-message", &signer, "hello", &signature])
+                .unwrap();
+            verify_message(&matches)
+        };
+        assert_eq!(verify_with(&key), Ok(()));
+        assert_eq!(
+            verify_with(&CombinedKey::generate_secp256k1()),
+            Err("Invalid signature")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Offline signing. A record template is built from a public key alone, signed on another machine
+//! and the detached signature is attached to produce the final ENR.
+use crate::keys::KeyType;
+use crate::seq;
+use crate::spec::EnrSpec;
+use crate::Enr;
+use alloy_rlp::{Decodable, Encodable, Header};
+use enr::{CombinedPublicKey, EnrPublicKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// Builds the record described by `spec` for `public_key`, signed by a throwaway key of the same
+/// type. The builder can only produce signed records, see `content_rlp` for the content to sign.
+pub fn placeholder_record(
+    spec: &EnrSpec,
+    public_key: &CombinedPublicKey,
+    allow_reserved: bool,
+) -> Result<Enr, String> {
+    spec.builder(public_key, allow_reserved)?
+        .build(&KeyType::of(public_key).generate())
+        .map_err(|e| format!("Failed to build ENR: {:?}", e))
+}
+
+/// Encodes the content list `[seq, k, v, ...]` of `enr` with its public key replaced by
+/// `public_key` of the same type.
+pub fn content_rlp(enr: &Enr, public_key: &CombinedPublicKey) -> Vec<u8> {
+    let identity_key = public_key.enr_key();
+    let mut payload = Vec::new();
+    enr.seq().encode(&mut payload);
+    for (key, value) in enr.iter() {
+        key.as_slice().encode(&mut payload);
+        if *key == identity_key {
+            public_key.encode().as_slice().encode(&mut payload);
+        } else {
+            payload.extend_from_slice(value);
+        }
+    }
+    let mut content = Vec::new();
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut content);
+    content.extend_from_slice(&payload);
+    content
+}
+
+/// An unsigned record: the RLP list `[seq, k, v, ...]` and the public key that must sign it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Template {
+    pub key_type: KeyType,
+    pub public_key: String,
+    pub content: String,
+    /// The keccak256 hash of the content, which secp256k1 keys sign. Ed25519 keys sign the content
+    /// itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_hash: Option<String>,
+}
+
+impl Template {
+    /// Builds the record described by `spec` for `public_key` without signing it.
+    pub fn new(
+        spec: &EnrSpec,
+        public_key: CombinedPublicKey,
+        allow_reserved: bool,
+    ) -> Result<Self, String> {
+        let placeholder = placeholder_record(spec, &public_key, allow_reserved)?;
+        let content = content_rlp(&placeholder, &public_key);
+
+        let key_type = KeyType::of(&public_key);
+        let signing_hash = match key_type {
+            KeyType::Secp256k1 => Some(format!("0x{}", hex::encode(Keccak256::digest(&content)))),
+            KeyType::Ed25519 => None,
+        };
+        Ok(Template {
+            key_type,
+            public_key: format!("0x{}", hex::encode(public_key.encode())),
+            content: format!("0x{}", hex::encode(content)),
+            signing_hash,
+        })
+    }
+
+    /// Adds a signature over the content, checking it against the template's public key.
+    pub fn attach(&self, signature: &[u8]) -> Result<Enr, &'static str> {
+        let public_key = parse_public_key(&self.public_key)?;
+        let content = hex::decode(self.content.trim_start_matches("0x"))
+            .map_err(|_| "Invalid template content hex")?;
+
+        assemble(&content, &public_key, signature)
+    }
+}
+
+/// Adds a signature to the record content `[seq, k, v, ...]`, checking it against `public_key`.
+pub fn assemble(
+    content: &[u8],
+    public_key: &CombinedPublicKey,
+    signature: &[u8],
+) -> Result<Enr, &'static str> {
+    let signature = match public_key {
+        // Signers commonly append a recovery id and may not normalize s.
+        CombinedPublicKey::Secp256k1(_) => {
+            let signature = match signature.len() {
+                64 | 65 => &signature[..64],
+                _ => return Err("secp256k1 signatures must be 64 or 65 bytes"),
+            };
+            let signature = k256::ecdsa::Signature::from_slice(signature)
+                .map_err(|_| "Invalid secp256k1 signature")?;
+            signature.normalize_s().unwrap_or(signature).to_vec()
+        }
+        CombinedPublicKey::Ed25519(_) => signature.to_vec(),
+    };
+    if !public_key.verify_v4(content, &signature) {
+        return Err("The signature was not made by the public key of the record");
+    }
+
+    // The record is the content list with the signature prepended.
+    let mut payload = content;
+    let header = Header::decode(&mut payload).map_err(|_| "Invalid record content")?;
+    if !header.list {
+        return Err("Invalid record content");
+    }
+    let mut record = Vec::new();
+    Header {
+        list: true,
+        payload_length: signature.as_slice().length() + payload.len(),
+    }
+    .encode(&mut record);
+    signature.as_slice().encode(&mut record);
+    record.extend_from_slice(payload);
+
+    let enr = Enr::decode(&mut record.as_slice()).map_err(|_| "Invalid record content")?;
+    if enr.public_key() != *public_key {
+        return Err("The record content holds a different public key");
+    }
+    Ok(enr)
+}
+
+/// Parses a hex encoded ed25519 (32 bytes) or secp256k1 (33, 64 or 65 bytes) public key.
+pub fn parse_public_key(input: &str) -> Result<CombinedPublicKey, &'static str> {
+    let mut bytes =
+        hex::decode(input.trim().trim_start_matches("0x")).map_err(|_| "Invalid public key hex")?;
+    match bytes.len() {
+        32 => {
+            let bytes = <[u8; 32]>::try_from(bytes.as_slice()).expect("length checked");
+            return ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+                .map(CombinedPublicKey::from)
+                .map_err(|_| "Invalid ed25519 public key");
+        }
+        64 => bytes.insert(0, 4),
+        33 | 65 => {}
+        _ => return Err("Public keys must be 32 (ed25519) or 33, 64 or 65 (secp256k1) bytes"),
+    }
+    k256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes)
+        .map(CombinedPublicKey::from)
+        .map_err(|_| "Invalid secp256k1 public key")
+}
+
+/// Prints or writes the template for `build --unsigned`.
+pub fn build_unsigned(
+    matches: &clap::ArgMatches,
+    spec: &EnrSpec,
+    public_key: CombinedPublicKey,
+) -> Result<(), String> {
+    let template = Template::new(spec, public_key, matches.get_flag("force"))?;
+    let template_json =
+        serde_json::to_string_pretty(&template).map_err(|_| "Cannot encode the template")?;
+
+    println!("Content to sign (RLP): {}", template.content);
+    match &template.signing_hash {
+        Some(signing_hash) => println!("Signing hash (keccak256): {}", signing_hash),
+        None => println!("Ed25519 keys sign the content itself"),
+    }
+    println!();
+    match matches.get_one::<String>("template-out") {
+        Some(template_out) => {
+            std::fs::write(template_out, template_json).map_err(|_| "Cannot write template")?;
+            println!("Template written to {}", template_out);
+        }
+        None => println!("Template:\n{}", template_json),
+    }
+    println!();
+    println!(
+        "Sign it with the node key, then run `enr-cli attach-signature <template> <signature>`"
+    );
+    if let Some(seq_state) = &spec.seq_state {
+        println!(
+            "Pass --seq-state {} to attach-signature to record the sequence number",
+            seq_state.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn attach_signature(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let template_file = matches
+        .get_one::<String>("template")
+        .expect("Must supply a template");
+    let template_json =
+        std::fs::read(Path::new(template_file)).map_err(|_| "Cannot read template file")?;
+    let template: Template =
+        serde_json::from_slice(&template_json).map_err(|_| "Invalid template file")?;
+    let signature = hex::decode(
+        matches
+            .get_one::<String>("signature")
+            .expect("Must supply a signature")
+            .trim()
+            .trim_start_matches("0x"),
+    )
+    .map_err(|_| "Invalid signature hex")?;
+
+    let enr = template.attach(&signature)?;
+    if let Some(enr_out) = matches.get_one::<String>("enr-out") {
+        std::fs::write(enr_out, enr.to_base64()).map_err(|_| "Cannot write ENR file")?;
+    }
+    seq::save_state(
+        matches.get_one::<String>("seq-state").map(Path::new),
+        enr.seq(),
+    )?;
+    println!("Signed ENR: {}", enr.to_base64());
+    println!();
+    crate::output::print_enr(enr);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrKey};
+
+    #[test]
+    fn test_detached_signatures_match_local_signing() {
+        let spec = EnrSpec {
+            seq: Some(5),
+            ip: Some("1.2.3.4".parse().unwrap()),
+            udp_port: Some(9000),
+            ..Default::default()
+        };
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let template = Template::new(&spec, key.public(), false).unwrap();
+            let content = hex::decode(template.content.trim_start_matches("0x")).unwrap();
+            let signature = key.sign_v4(&content).unwrap();
+
+            let enr = template.attach(&signature).unwrap();
+            assert!(enr.verify());
+            assert_eq!(enr.seq(), 5);
+            assert_eq!(enr.udp4(), Some(9000));
+            assert_eq!(enr.public_key(), key.public());
+
+            let other_signature = KeyType::of(&key.public())
+                .generate()
+                .sign_v4(&content)
+                .unwrap();
+            assert!(template.attach(&other_signature).is_err());
+        }
+    }
+}
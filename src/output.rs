@@ -0,0 +1,226 @@
+//! Output formats shared by the commands that print records.
+use crate::enode::Enode;
+use crate::enr_ext::EnrExt;
+use crate::eth2_ext::Eth2Enr;
+use crate::keys::KeyType;
+use crate::Enr;
+use serde_json::{json, Value};
+use ssz::Encode;
+use std::str::FromStr;
+
+/// The ways a record can be printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable summary of the record.
+    Text,
+    /// The base64 `enr:` string only, one per line.
+    Enr,
+    /// A JSON object per record.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "enr" => Ok(OutputFormat::Enr),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err("Unknown output format"),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The values accepted by the `--format` CLI flag.
+    pub const VARIANTS: [&'static str; 3] = ["text", "enr", "json"];
+
+    /// Reads the `format` flag from the matches, defaulting to [`OutputFormat::Text`].
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Self, &'static str> {
+        matches
+            .get_one::<String>("format")
+            .map(|f| f.parse())
+            .unwrap_or(Ok(OutputFormat::Text))
+    }
+}
+
+/// Builds the JSON representation of an ENR.
+fn enr_json(enr: &Enr) -> Value {
+    let mut value = json!({
+        "enr": enr.to_base64(),
+        "seq": enr.seq(),
+        "key_type": KeyType::of(&enr.public_key()).to_string(),
+        "node_id": hex::encode(enr.node_id().raw()),
+        "peer_id": enr.peer_id().to_string(),
+        "enode": enr.enode_id(),
+        "multiaddrs": enr
+            .multiaddr()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    });
+    let object = value.as_object_mut().expect("is an object");
+    if let Some(ip) = enr.ip4() {
+        object.insert("ip4".into(), json!(ip.to_string()));
+    }
+    if let Some(ip) = enr.ip6() {
+        object.insert("ip6".into(), json!(ip.to_string()));
+    }
+    if let Some(tcp) = enr.tcp4() {
+        object.insert("tcp4".into(), json!(tcp));
+    }
+    if let Some(tcp) = enr.tcp6() {
+        object.insert("tcp6".into(), json!(tcp));
+    }
+    if let Some(udp) = enr.udp4() {
+        object.insert("udp4".into(), json!(udp));
+    }
+    if let Some(udp) = enr.udp6() {
+        object.insert("udp6".into(), json!(udp));
+    }
+    if let Some(quic) = enr.quic4() {
+        object.insert("quic4".into(), json!(quic));
+    }
+    if let Some(quic) = enr.quic6() {
+        object.insert("quic6".into(), json!(quic));
+    }
+    if let Ok(enr_fork_id) = enr.eth2() {
+        object.insert(
+            "eth2".into(),
+            json!({
+                "fork_digest": hex::encode(enr_fork_id.fork_digest),
+                "next_fork_version": hex::encode(enr_fork_id.next_fork_version),
+                "next_fork_epoch": enr_fork_id.next_fork_epoch,
+                "ssz": hex::encode(enr_fork_id.as_ssz_bytes()),
+            }),
+        );
+    }
+    value
+}
+
+/// Builds the JSON representation of the partial record held in an enode URL.
+fn enode_json(enode: &Enode) -> Value {
+    let mut value = json!({
+        "enode": enode.to_string(),
+        "node_id": hex::encode(enode.node_id().raw()),
+        "public_key": hex::encode(enode.public_key_bytes()),
+        "peer_id": enode.peer_id().to_string(),
+        "multiaddrs": enode
+            .multiaddr()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    });
+    let object = value.as_object_mut().expect("is an object");
+    if let Some(ip) = enode.ip {
+        object.insert("ip".into(), json!(ip.to_string()));
+    }
+    if let Some(tcp) = enode.tcp {
+        object.insert("tcp".into(), json!(tcp));
+    }
+    if let Some(udp) = enode.udp {
+        object.insert("udp".into(), json!(udp));
+    }
+    value
+}
+
+/// Prints an enode in the requested format. Enodes have no signed record, so the `enr` format
+/// prints the URL itself.
+pub fn print_enode_as(enode: &Enode, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => print_enode(enode),
+        OutputFormat::Enr => println!("{}", enode),
+        OutputFormat::Json => println!("{}", enode_json(enode)),
+    }
+}
+
+/// Prints an ENR in the requested format.
+pub fn print_enr_as(enr: Enr, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => print_enr(enr),
+        OutputFormat::Enr => println!("{}", enr.to_base64()),
+        OutputFormat::Json => println!("{}", enr_json(&enr)),
+    }
+}
+
+pub fn print_enr(enr: Enr) {
+    println!("ENR Read:");
+    println!("Sequence No:{}", enr.seq());
+    println!("Key Type: {}", KeyType::of(&enr.public_key()));
+    println!("NodeId: {}", hex::encode(enr.node_id().raw()));
+    if let Some(enode) = enr.enode_id() {
+        println!("EnodeId: {}", enode);
+    }
+    println!("Libp2p PeerId: {}", enr.peer_id());
+    if let Some(ip) = enr.ip4() {
+        println!("ipv4:{:?}", ip);
+    }
+    if let Some(ip) = enr.ip6() {
+        println!("ipv6:{:?}", ip);
+    }
+    if let Some(tcp) = enr.tcp4() {
+        println!("v4_tcp:{}", tcp);
+    }
+    if let Some(tcp) = enr.tcp6() {
+        println!("v6_tcp:{}", tcp);
+    }
+    if let Some(udp) = enr.udp4() {
+        println!("v4_udp:{}", udp);
+    }
+    if let Some(udp) = enr.udp6() {
+        println!("v6_udp:{}", udp);
+    }
+    if let Some(udp) = enr.quic4() {
+        println!("v4_quic:{}", udp);
+    }
+    if let Some(udp) = enr.quic6() {
+        println!("v6_quic:{}", udp);
+    }
+
+    if let Ok(enr_fork_id) = enr.eth2() {
+        println!(
+            "Eth2 Field:\n\tFork digest: {}\n\tNext fork version: {}\n\tNext fork epoch: {}\n\tSSZ Bytes: {}",
+            hex::encode(enr_fork_id.fork_digest),
+            hex::encode(enr_fork_id.next_fork_version),
+            enr_fork_id.next_fork_epoch,
+            hex::encode(enr_fork_id.as_ssz_bytes())
+        );
+    }
+
+    let multiaddrs = enr.multiaddr();
+    if !multiaddrs.is_empty() {
+        println!("Known multiaddrs:");
+        for multiaddr in multiaddrs {
+            println!("{}", multiaddr);
+        }
+    }
+}
+
+/// Prints the partial record held in an enode URL.
+pub fn print_enode(enode: &Enode) {
+    println!("Enode Read (partial record, no signature):");
+    println!("NodeId: {}", hex::encode(enode.node_id().raw()));
+    println!("Public Key: {}", hex::encode(enode.public_key_bytes()));
+    println!("EnodeId: {}", enode);
+    println!("Libp2p PeerId: {}", enode.peer_id());
+    match enode.ip {
+        Some(std::net::IpAddr::V4(ip)) => println!("ipv4:{:?}", ip),
+        Some(std::net::IpAddr::V6(ip)) => println!("ipv6:{:?}", ip),
+        None => {}
+    }
+    if let Some(tcp) = enode.tcp {
+        println!("tcp:{}", tcp);
+    }
+    if let Some(udp) = enode.udp {
+        println!("udp:{}", udp);
+    }
+
+    let multiaddrs = enode.multiaddr();
+    if !multiaddrs.is_empty() {
+        println!("Known multiaddrs:");
+        for multiaddr in multiaddrs {
+            println!("{}", multiaddr);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Moves the fields of a record to a new node key, for when the old key is compromised.
+use crate::enr_ext::EnrExt;
+use crate::field::RESERVED_KEYS;
+use crate::key_format::{self, ClientFormat};
+use crate::keys::{self, KeyType};
+use crate::Enr;
+use bytes::Bytes;
+use enr::{CombinedKey, EnrKey, EnrPublicKey};
+use std::path::Path;
+
+/// Builds a record signed by `new_key` holding every non-identity key of `enr`, with the same
+/// sequence number.
+pub fn rotate_record(enr: &Enr, new_key: &CombinedKey) -> Result<Enr, String> {
+    let mut builder = enr::Builder::default();
+    builder.seq(enr.seq());
+    for (key, value) in enr.iter() {
+        if !RESERVED_KEYS
+            .iter()
+            .any(|id| id.as_bytes() == key.as_slice())
+        {
+            builder.add_value_rlp(key, Bytes::copy_from_slice(value));
+        }
+    }
+    builder
+        .build(new_key)
+        .map_err(|e| format!("Failed to build ENR: {:?}", e))
+}
+
+pub fn rotate(matches: &clap::ArgMatches) -> Result<(), String> {
+    let enr = matches
+        .get_one::<String>("enr")
+        .expect("Must supply an ENR")
+        .parse::<Enr>()
+        .map_err(|_| "Invalid ENR")?;
+    if !enr.verify() {
+        return Err("The ENR has an invalid signature".into());
+    }
+    let old_key_type =
+        KeyType::from_matches(matches)?.unwrap_or_else(|| KeyType::of(&enr.public_key()));
+    let old_key = keys::load_key_as(matches, Some(old_key_type))?;
+    if old_key
+        .as_ref()
+        .is_some_and(|key| key.public().encode() != enr.public_key().encode())
+    {
+        return Err("The key does not match the record".into());
+    }
+
+    let new_key_type = matches
+        .get_one::<String>("new-key-type")
+        .map(|key_type| key_type.parse::<KeyType>())
+        .transpose()?
+        .unwrap_or_else(|| KeyType::of(&enr.public_key()));
+    let new_key = match matches.get_one::<String>("new-key") {
+        Some(key_file) => keys::read_key_file(Path::new(key_file), Some(new_key_type))?,
+        None => new_key_type.generate(),
+    };
+    if new_key.public().encode() == enr.public_key().encode() {
+        return Err("The new key is the key of the record".into());
+    }
+    let new_enr = rotate_record(&enr, &new_key)?;
+
+    let format = matches
+        .get_one::<String>("format")
+        .map_or(Ok(ClientFormat::Lighthouse), |format| format.parse())?;
+    if let Some(out) = matches.get_one::<String>("old-key-out") {
+        let old_key = old_key.as_ref().ok_or("--old-key-out needs the old key")?;
+        let bytes = key_format::encode_client_key(old_key, format, matches)?;
+        keys::write_secret_file(Path::new(out), &bytes)?;
+    }
+    if let Some(out) = matches.get_one::<String>("new-key-out") {
+        let bytes = key_format::encode_client_key(&new_key, format, matches)?;
+        keys::write_secret_file(Path::new(out), &bytes)?;
+    }
+    if let Some(enr_out) = matches.get_one::<String>("enr-out") {
+        std::fs::write(enr_out, new_enr.to_base64()).map_err(|_| "Cannot write ENR file")?;
+    }
+
+    println!("Old NodeId: {}", hex::encode(enr.node_id().raw()));
+    println!("Old Libp2p PeerId: {}", enr.peer_id());
+    println!("New NodeId: {}", hex::encode(new_enr.node_id().raw()));
+    println!("New Libp2p PeerId: {}", new_enr.peer_id());
+    println!();
+    println!("Rotated ENR: {}", new_enr.to_base64());
+    if !matches.contains_id("new-key") && !matches.contains_id("new-key-out") {
+        println!("Warning: the new key was generated and not saved, use --new-key-out to keep it");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_the_fields() {
+        let old_key = CombinedKey::generate_secp256k1();
+        let enr = enr::Builder::default()
+            .seq(7)
+            .ip4("10.0.0.1".parse().unwrap())
+            .udp4(9000)
+            .add_value("eth2", &Bytes::from_static(&[1, 2, 3]))
+            .build(&old_key)
+            .unwrap();
+
+        let new_key = CombinedKey::generate_ed25519();
+        let rotated = rotate_record(&enr, &new_key).unwrap();
+        assert!(rotated.verify());
+        assert_eq!(rotated.public_key(), new_key.public());
+        assert_ne!(rotated.node_id(), enr.node_id());
+        assert_eq!(rotated.seq(), 7);
+        assert_eq!(rotated.ip4(), enr.ip4());
+        assert_eq!(rotated.udp4(), Some(9000));
+        assert_eq!(rotated.get_raw_rlp("eth2"), enr.get_raw_rlp("eth2"));
+        assert!(rotated.get_raw_rlp("secp256k1").is_none());
+    }
+}
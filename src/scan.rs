@@ -0,0 +1,272 @@
+//! Extracts ENRs, enode URLs and `/p2p/` multiaddrs from arbitrary text such as client logs.
+use crate::enode::Enode;
+use crate::enr_ext::{peer_id_to_public_key, CombinedKeyPublicExt, EnrExt};
+use crate::output::{print_enode_as, print_enr_as, OutputFormat};
+use crate::Enr;
+use enr::{CombinedPublicKey, NodeId};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use libp2p_identity::PeerId;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// A node identity found in the scanned text.
+#[derive(Debug, Clone)]
+pub enum Found {
+    /// A complete, signed record.
+    Enr(Enr),
+    /// An `enode://` URL.
+    Enode(Enode),
+    /// A multiaddr that ends in a `/p2p/` peer id.
+    Multiaddr {
+        multiaddr: Multiaddr,
+        public_key: CombinedPublicKey,
+    },
+}
+
+impl Found {
+    /// The node id of the identity that was found.
+    pub fn node_id(&self) -> NodeId {
+        match self {
+            Found::Enr(enr) => enr.node_id(),
+            Found::Enode(enode) => enode.node_id(),
+            Found::Multiaddr { public_key, .. } => NodeId::from(public_key.clone()),
+        }
+    }
+
+    /// The libp2p peer id of the identity that was found.
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            Found::Enr(enr) => enr.peer_id(),
+            Found::Enode(enode) => enode.peer_id(),
+            Found::Multiaddr { public_key, .. } => public_key.as_peer_id(),
+        }
+    }
+
+    /// Orders findings for the same node. Signed records beat bare addresses and a higher
+    /// sequence number beats a lower one.
+    fn rank(&self) -> (bool, u64) {
+        match self {
+            Found::Enr(enr) => (true, enr.seq()),
+            _ => (false, 0),
+        }
+    }
+}
+
+/// Searches `text` for node identities. The result is deduplicated by node id, keeping the highest
+/// ranked finding, in the order in which nodes first appear.
+pub fn scan_text(text: &str) -> Vec<Found> {
+    let mut tokens: Vec<(usize, Found)> = Vec::new();
+    tokens.extend(find_enrs(text));
+    tokens.extend(find_enodes(text));
+    tokens.extend(find_multiaddrs(text));
+    tokens.sort_by_key(|(position, _)| *position);
+
+    let mut found: Vec<Found> = Vec::new();
+    let mut index: HashMap<NodeId, usize> = HashMap::new();
+    for (_, item) in tokens {
+        match index.get(&item.node_id()) {
+            Some(&i) => {
+                if item.rank() > found[i].rank() {
+                    found[i] = item;
+                }
+            }
+            None => {
+                index.insert(item.node_id(), found.len());
+                found.push(item);
+            }
+        }
+    }
+    found
+}
+
+/// Returns the end of the run of characters matching `allowed` that starts at `start`.
+fn token_end(text: &str, start: usize, allowed: impl Fn(char) -> bool) -> usize {
+    text[start..]
+        .char_indices()
+        .find(|(_, c)| !allowed(*c))
+        .map(|(i, _)| start + i)
+        .unwrap_or(text.len())
+}
+
+fn find_enrs(text: &str) -> Vec<(usize, Found)> {
+    text.match_indices("enr:")
+        .filter_map(|(start, prefix)| {
+            let end = token_end(text, start + prefix.len(), |c| {
+                c.is_ascii_alphanumeric() || c == '-' || c == '_'
+            });
+            text[start..end]
+                .parse::<Enr>()
+                .ok()
+                .map(|enr| (start, Found::Enr(enr)))
+        })
+        .collect()
+}
+
+fn find_enodes(text: &str) -> Vec<(usize, Found)> {
+    text.match_indices("enode://")
+        .filter_map(|(start, prefix)| {
+            let end = token_end(text, start + prefix.len(), |c| {
+                c.is_ascii_alphanumeric() || "@.:[]?=&%".contains(c)
+            });
+            let mut url = text[start..end].trim_end_matches(['.', ':']);
+            // Drop a closing bracket that belongs to the surrounding text.
+            if url.matches(']').count() > url.matches('[').count() {
+                url = &url[..url.len() - 1];
+            }
+            url.parse::<Enode>()
+                .ok()
+                .map(|enode| (start, Found::Enode(enode)))
+        })
+        .collect()
+}
+
+fn find_multiaddrs(text: &str) -> Vec<(usize, Found)> {
+    let is_multiaddr_char = |c: char| c.is_ascii_alphanumeric() || "/.:-_".contains(c);
+    text.match_indices("/p2p/")
+        .filter_map(|(p2p_start, prefix)| {
+            let end = token_end(text, p2p_start + prefix.len(), |c| {
+                c.is_ascii_alphanumeric()
+            });
+            // Walk back to the start of the multiaddr that holds the peer id.
+            let token_start = text[..p2p_start]
+                .char_indices()
+                .rev()
+                .find(|(_, c)| !is_multiaddr_char(*c))
+                .map(|(i, c)| i + c.len_utf8())
+                .unwrap_or(0);
+            let start = text[token_start..p2p_start]
+                .find('/')
+                .map(|i| token_start + i)
+                .unwrap_or(p2p_start);
+
+            let multiaddr = text[start..end]
+                .parse::<Multiaddr>()
+                .or_else(|_| text[p2p_start..end].parse::<Multiaddr>())
+                .ok()?;
+            let peer_id = multiaddr.iter().find_map(|protocol| match protocol {
+                Protocol::P2p(peer_id) => Some(peer_id),
+                _ => None,
+            })?;
+            let public_key = peer_id_to_public_key(&peer_id).ok()?;
+            Some((
+                start,
+                Found::Multiaddr {
+                    multiaddr,
+                    public_key,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Prints a finding in the requested format. Records and enodes print as `read` would print them,
+/// multiaddrs with the identity behind their peer id.
+fn print_found(found: Found, format: OutputFormat) {
+    let (node_id, peer_id) = (found.node_id(), found.peer_id());
+    match found {
+        Found::Enr(enr) => print_enr_as(enr, format),
+        Found::Enode(enode) => print_enode_as(&enode, format),
+        Found::Multiaddr { multiaddr, .. } => match format {
+            OutputFormat::Enr => println!("{}", multiaddr),
+            OutputFormat::Text => {
+                println!("Multiaddr: {}", multiaddr);
+                println!("NodeId: {}", hex::encode(node_id.raw()));
+                println!("Libp2p PeerId: {}", peer_id);
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "node_id": hex::encode(node_id.raw()),
+                    "peer_id": peer_id.to_string(),
+                    "multiaddr": multiaddr.to_string(),
+                })
+            ),
+        },
+    }
+}
+
+pub fn scan(matches: &clap::ArgMatches) -> Result<(), &'static str> {
+    let format = OutputFormat::from_matches(matches)?;
+
+    let mut text = String::new();
+    let files: Vec<&String> = matches
+        .get_many::<String>("input")
+        .map(|files| files.collect())
+        .unwrap_or_default();
+    if files.is_empty() || files.iter().any(|file| *file == "-") {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|_| "Cannot read stdin")?;
+        text.push_str(&String::from_utf8_lossy(&bytes));
+    }
+    for file in files.into_iter().filter(|file| *file != "-") {
+        let mut bytes = Vec::new();
+        File::open(file)
+            .map_err(|_| "Cannot find input file")?
+            .read_to_end(&mut bytes)
+            .map_err(|_| "Cannot read input file")?;
+        // Keep tokens in separate files apart.
+        text.push('\n');
+        text.push_str(&String::from_utf8_lossy(&bytes));
+    }
+
+    let found = scan_text(&text);
+    if format == OutputFormat::Text {
+        println!("Found {} unique node(s)", found.len());
+    }
+    for item in found {
+        if format == OutputFormat::Text {
+            println!();
+        }
+        print_found(item, format);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENR: &str = "enr:-Ku4QJsxkOibTc9FXfBWYmcdMAGwH4bnOOFb4BlTHfMdx_f0WN-u4IUqZcQVP9iuEyoxipFs7-Qd_rH_0HfyOQitc7IBh2F0dG5ldHOIAAAAAAAAAACEZXRoMpD1pf1CAAAAAP__________gmlkgnY0gmlwhLAJM9iJc2VjcDI1NmsxoQL2RyM26TKZzqnUsyycHQB4jnyg6Wi79rwLXtaZXty06YN1ZHCCW8w";
+    const PEER_ID: &str = "16Uiu2HAmC13Brucnz5qR8caKi8qKK6766PFoxsF5MzK2RvbTyBRr";
+
+    #[test]
+    fn test_scan_log_line() {
+        let text = format!(
+            "Dec 01 12:00:00.000 INFO ENR Initialised enr: {}, seq: 1, id: 0x3ab5..1447, ip4: Some(176.9.51.216)",
+            ENR
+        );
+        let found = scan_text(&text);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(&found[0], Found::Enr(enr) if enr.to_base64() == ENR));
+    }
+
+    #[test]
+    fn test_scan_dedupes_by_node_id() {
+        let text = format!(
+            "peer connected \"/ip4/176.9.51.216/tcp/9000/p2p/{}\"\nbootnodes = [\"{}\"]",
+            PEER_ID, ENR
+        );
+        let found = scan_text(&text);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(found[0], Found::Enr(_)));
+        assert_eq!(found[0].peer_id().to_string(), PEER_ID);
+    }
+
+    #[test]
+    fn test_scan_multiaddr() {
+        let text = format!("dialing (/ip6/::1/udp/9000/quic-v1/p2p/{}).", PEER_ID);
+        let found = scan_text(&text);
+        assert_eq!(found.len(), 1);
+        match &found[0] {
+            Found::Multiaddr { multiaddr, .. } => assert_eq!(
+                multiaddr.to_string(),
+                format!("/ip6/::1/udp/9000/quic-v1/p2p/{}", PEER_ID)
+            ),
+            other => panic!("unexpected finding {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Chooses the sequence number of a new version of a record, so peers don't ignore the update.
+use crate::Enr;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the sequence number of a new record is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeqPolicy {
+    /// One more than the previous record's, from the previous ENR or the state file.
+    Increment,
+    /// The current unix time in seconds, as several clients do.
+    Timestamp,
+    /// The value given by `--seq-no` or the spec file.
+    Explicit,
+}
+
+impl FromStr for SeqPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "increment" => Ok(SeqPolicy::Increment),
+            "timestamp" => Ok(SeqPolicy::Timestamp),
+            "explicit" => Ok(SeqPolicy::Explicit),
+            _ => Err("Unknown sequence number policy"),
+        }
+    }
+}
+
+impl SeqPolicy {
+    /// The values accepted by the `--seq-policy` flag.
+    pub const VARIANTS: [&'static str; 3] = ["increment", "timestamp", "explicit"];
+
+    /// Reads `--seq-policy`, if given.
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Option<Self>, &'static str> {
+        matches
+            .get_one::<String>("seq-policy")
+            .map(|policy| policy.parse())
+            .transpose()
+    }
+
+    /// The sequence number following `previous` under this policy.
+    pub fn next(self, previous: Option<u64>, explicit: Option<u64>) -> Result<u64, &'static str> {
+        match self {
+            SeqPolicy::Increment => previous
+                .map_or(Some(1), |seq| seq.checked_add(1))
+                .ok_or("Sequence number overflow"),
+            SeqPolicy::Timestamp => Ok(SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| "The system clock is before the unix epoch")?
+                .as_secs()),
+            SeqPolicy::Explicit => explicit.ok_or("The explicit policy needs --seq-no"),
+        }
+    }
+}
+
+/// Reads a previous record given as a base64 ENR or a file holding one, e.g. a Lighthouse
+/// `enr.dat`.
+pub fn read_previous_enr(value: &str) -> Result<Enr, &'static str> {
+    if let Ok(enr) = value.trim().parse::<Enr>() {
+        return Ok(enr);
+    }
+    fs::read_to_string(value)
+        .map_err(|_| "The previous ENR is neither a record nor a readable file")?
+        .trim()
+        .parse::<Enr>()
+        .map_err(|_| "Invalid previous ENR")
+}
+
+/// Reads the sequence number kept in `--seq-state`. A missing file has no sequence number yet.
+fn read_state(path: &Path) -> Result<Option<u64>, &'static str> {
+    match fs::read_to_string(path) {
+        Ok(state) => state
+            .trim()
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| "The sequence state file does not hold a number"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(_) => Err("Cannot read the sequence state file"),
+    }
+}
+
+/// Picks the sequence number of a new record given a policy and a state file. The previous
+/// sequence number is the highest of `previous` and the state file. Without a policy, `explicit`
+/// wins, then `default` if there is a previous sequence number. Warns if the new sequence number
+/// does not move past the previous one.
+pub fn next_seq(
+    policy: Option<SeqPolicy>,
+    state: Option<&Path>,
+    previous: Option<u64>,
+    explicit: Option<u64>,
+    default: Option<SeqPolicy>,
+) -> Result<Option<u64>, &'static str> {
+    let state = match state {
+        Some(path) => read_state(path)?,
+        None => None,
+    };
+    let previous = previous.max(state);
+    let policy = match policy {
+        Some(policy) => Some(policy),
+        None if explicit.is_some() => Some(SeqPolicy::Explicit),
+        None => default.filter(|_| previous.is_some()),
+    };
+    let Some(policy) = policy else {
+        return Ok(explicit);
+    };
+    let seq = policy.next(previous, explicit)?;
+    if let Some(previous) = previous.filter(|previous| seq <= *previous) {
+        println!(
+            "Warning: the sequence number {} is not greater than the previous {}, peers will keep the previous record",
+            seq, previous
+        );
+    }
+    Ok(Some(seq))
+}
+
+/// Records the sequence number of a signed record in the state file, if there is one.
+pub fn save_state(state: Option<&Path>, seq: u64) -> Result<(), &'static str> {
+    match state {
+        Some(path) => fs::write(path, format!("{}\n", seq))
+            .map_err(|_| "Cannot write the sequence state file"),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_policies() {
+        assert_eq!(SeqPolicy::Increment.next(None, None), Ok(1));
+        assert_eq!(SeqPolicy::Increment.next(Some(4), Some(9)), Ok(5));
+        assert!(SeqPolicy::Increment.next(Some(u64::MAX), None).is_err());
+        assert_eq!(SeqPolicy::Explicit.next(Some(4), Some(9)), Ok(9));
+        assert!(SeqPolicy::Explicit.next(Some(4), None).is_err());
+        // Well past any sequence number an incrementing node reaches.
+        assert!(SeqPolicy::Timestamp.next(None, None).unwrap() > 1_700_000_000);
+    }
+}
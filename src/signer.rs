@@ -0,0 +1,169 @@
+//! Signs records with keys that are not held by the CLI.
+use crate::offline;
+use crate::spec::EnrSpec;
+use crate::Enr;
+use enr::{CombinedKey, CombinedPublicKey, EnrKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+/// A backend holding a node key.
+pub trait Signer {
+    /// The public key of the node key.
+    fn public_key(&self) -> Result<CombinedPublicKey, String>;
+
+    /// Signs the RLP content `[seq, k, v, ...]` of a record with the `v4` identity scheme.
+    fn sign_v4(&self, content: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Signs a record that was built or modified with a throwaway key of the same type as
+    /// `public_key`, which replaces the throwaway key in the signed record.
+    fn sign(&self, placeholder: &Enr, public_key: &CombinedPublicKey) -> Result<Enr, String> {
+        let content = offline::content_rlp(placeholder, public_key);
+        let signature = self.sign_v4(&content)?;
+        offline::assemble(&content, public_key, &signature).map_err(Into::into)
+    }
+
+    /// Builds and signs the record described by `spec`.
+    fn build(&self, spec: &EnrSpec, allow_reserved: bool) -> Result<Enr, String> {
+        let public_key = self.public_key()?;
+        let placeholder = offline::placeholder_record(spec, &public_key, allow_reserved)?;
+        self.sign(&placeholder, &public_key)
+    }
+}
+
+impl Signer for CombinedKey {
+    fn public_key(&self) -> Result<CombinedPublicKey, String> {
+        Ok(self.public())
+    }
+
+    fn sign_v4(&self, content: &[u8]) -> Result<Vec<u8>, String> {
+        EnrKey::sign_v4(self, content).map_err(|_| "Failed to sign the record".to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct PublicKeyResponse {
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    content: String,
+    /// The keccak256 hash of the content, which secp256k1 keys sign.
+    signing_hash: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+/// A signing service reached over HTTP.
+///
+/// - `GET <url>/public_key` returns `{"public_key": "0x..."}`.
+/// - `POST <url>/sign` with `{"content": "0x...", "signing_hash": "0x..."}` returns
+///   `{"signature": "0x..."}`.
+pub struct RemoteSigner {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str) -> Self {
+        RemoteSigner {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(std::time::Duration::from_secs(30))
+                .build(),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> Result<CombinedPublicKey, String> {
+        let response: PublicKeyResponse = self
+            .agent
+            .get(&format!("{}/public_key", self.url))
+            .call()
+            .map_err(|e| format!("Remote signer request failed: {}", e))?
+            .into_json()
+            .map_err(|_| "Invalid public key response from the remote signer")?;
+        offline::parse_public_key(&response.public_key).map_err(Into::into)
+    }
+
+    fn sign_v4(&self, content: &[u8]) -> Result<Vec<u8>, String> {
+        let request = SignRequest {
+            content: format!("0x{}", hex::encode(content)),
+            signing_hash: format!("0x{}", hex::encode(Keccak256::digest(content))),
+        };
+        let response: SignResponse = self
+            .agent
+            .post(&format!("{}/sign", self.url))
+            .send_json(&request)
+            .map_err(|e| format!("Remote signer request failed: {}", e))?
+            .into_json()
+            .map_err(|_| "Invalid signature response from the remote signer")?;
+        hex::decode(response.signature.trim_start_matches("0x"))
+            .map_err(|_| "Invalid signature hex from the remote signer".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::EnrPublicKey;
+    use serde_json::{json, Value};
+
+    /// Serves the remote signer API for `key` until the test exits.
+    fn mock_signer(key: CombinedKey) -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let body = match request.url() {
+                    "/public_key" => json!({ "public_key": hex::encode(key.public().encode()) }),
+                    "/sign" => {
+                        let sign_request: Value =
+                            serde_json::from_reader(request.as_reader()).unwrap();
+                        let content = hex::decode(
+                            sign_request["content"]
+                                .as_str()
+                                .unwrap()
+                                .trim_start_matches("0x"),
+                        )
+                        .unwrap();
+                        json!({ "signature": hex::encode(EnrKey::sign_v4(&key, &content).unwrap()) })
+                    }
+                    _ => json!({}),
+                };
+                let _ = request.respond(tiny_http::Response::from_string(body.to_string()));
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_remote_signer_builds_and_updates_records() {
+        let key = CombinedKey::generate_secp256k1();
+        let signer = RemoteSigner::new(&mock_signer(
+            CombinedKey::secp256k1_from_bytes(&mut key.encode()).unwrap(),
+        ));
+        let spec = EnrSpec {
+            udp_port: Some(9000),
+            ..Default::default()
+        };
+
+        let enr = signer.build(&spec, false).unwrap();
+        assert!(enr.verify());
+        assert_eq!(enr.public_key(), key.public());
+        assert_eq!(enr.udp4(), Some(9000));
+
+        let placeholder_key = CombinedKey::generate_secp256k1();
+        let mut placeholder = enr.clone();
+        placeholder.set_udp4(9001, &placeholder_key).unwrap();
+        let updated = signer.sign(&placeholder, &key.public()).unwrap();
+        assert!(updated.verify());
+        assert_eq!(updated.node_id(), enr.node_id());
+        assert_eq!(updated.udp4(), Some(9001));
+        assert_eq!(updated.seq(), enr.seq() + 1);
+    }
+}
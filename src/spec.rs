@@ -0,0 +1,437 @@
+//! Declarative descriptions of an ENR, loaded from TOML, YAML or JSON files.
+//!
+//! ```toml
+//! seq = 2
+//! seq_policy = "increment"
+//! seq_state = "seq.txt"
+//! ip = "1.2.3.4"
+//! udp_port = 9000
+//! listen_addrs = ["/ip4/1.2.3.4/udp/9001/quic-v1"]
+//! eth2 = "f5a5fd4200000000ffffffffffffffff"
+//! attnets = [0, 17]
+//! key_file = "key.dat"
+//!
+//! [fields]
+//! client = "string:lighthouse"
+//! ```
+use crate::build::Addresses;
+use crate::enr_ext::CombinedKeyPublicExt;
+use crate::eth2_ext::{EnrForkId, BITFIELD_ENR_KEY, ETH2_ENR_KEY, SYNC_COMMITTEE_BITFIELD_ENR_KEY};
+use crate::field::Field;
+use crate::keys::KeyType;
+use crate::seq::SeqPolicy;
+use crate::Enr;
+use bytes::Bytes;
+use enr::{CombinedKey, CombinedPublicKey, EnrKey};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use serde::{Deserialize, Serialize};
+use ssz::Decode;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+/// The number of attestation subnets in the `attnets` bitfield.
+const ATTESTATION_SUBNET_COUNT: u64 = 64;
+/// The number of sync committee subnets in the `syncnets` bitfield.
+const SYNC_COMMITTEE_SUBNET_COUNT: u64 = 4;
+
+/// All the inputs needed to build an ENR.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnrSpec {
+    /// The sequence number of the record.
+    pub seq: Option<u64>,
+    /// How the sequence number is chosen when the record replaces an earlier one.
+    pub seq_policy: Option<SeqPolicy>,
+    /// A file keeping the last sequence number used.
+    pub seq_state: Option<PathBuf>,
+    pub ip: Option<Ipv4Addr>,
+    pub ip6: Option<Ipv6Addr>,
+    pub tcp_port: Option<u16>,
+    pub tcp6_port: Option<u16>,
+    pub udp_port: Option<u16>,
+    pub udp6_port: Option<u16>,
+    pub quic_port: Option<u16>,
+    pub quic6_port: Option<u16>,
+    /// Multiaddrs that are mapped onto the IP and port keys.
+    pub listen_addrs: Vec<String>,
+    /// The hex encoded SSZ bytes of the eth2 `EnrForkId`.
+    pub eth2: Option<String>,
+    /// The attestation subnets the node is subscribed to.
+    pub attnets: Option<Vec<u64>>,
+    /// The sync committee subnets the node is subscribed to.
+    pub syncnets: Option<Vec<u64>>,
+    /// Custom keys in the `type:value` form accepted by `--field`.
+    pub fields: BTreeMap<String, String>,
+    /// The key used to sign the record.
+    pub key_file: Option<PathBuf>,
+    /// The type of the key in `key_file`, or of the generated key if there is none.
+    pub key_type: Option<KeyType>,
+}
+
+impl EnrSpec {
+    /// Loads a spec from a `.toml`, `.yaml`/`.yml` or `.json` file. A relative `key_file` or
+    /// `seq_state` is resolved against the directory of the spec file.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read spec file {}: {}", path.display(), e))?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        let mut spec: EnrSpec = match extension {
+            "toml" => toml::from_str(&contents).map_err(|e| e.to_string()),
+            "yaml" | "yml" => serde_yaml_ng::from_str(&contents).map_err(|e| e.to_string()),
+            "json" => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            _ => Err("Spec files must have a .toml, .yaml, .yml or .json extension".to_string()),
+        }
+        .map_err(|e| format!("Invalid spec file {}: {}", path.display(), e))?;
+
+        if let Some(directory) = path.parent() {
+            spec.key_file = spec.key_file.map(|key_file| directory.join(key_file));
+            spec.seq_state = spec.seq_state.map(|seq_state| directory.join(seq_state));
+        }
+        Ok(spec)
+    }
+
+    /// The port field set by a `/tcp` or `/udp` (optionally `/quic-v1`) multiaddr component.
+    fn port_field(&mut self, tcp: bool, ip4: bool, quic: bool) -> &mut Option<u16> {
+        match (tcp, ip4, quic) {
+            (true, true, _) => &mut self.tcp_port,
+            (true, false, _) => &mut self.tcp6_port,
+            (false, true, false) => &mut self.udp_port,
+            (false, false, false) => &mut self.udp6_port,
+            (false, true, true) => &mut self.quic_port,
+            (false, false, true) => &mut self.quic6_port,
+        }
+    }
+
+    /// Makes the addresses in `overrides` win over this spec's: the IPs and ports of its listen
+    /// addresses are replaced by those set in `overrides`, and its IP and port fields that the
+    /// listen addresses of `overrides` set are cleared.
+    fn override_addresses(&mut self, overrides: &mut EnrSpec) {
+        for listen_addr in &mut self.listen_addrs {
+            let Ok(multiaddr) = listen_addr.parse::<Multiaddr>() else {
+                continue;
+            };
+            let quic = multiaddr.iter().any(|p| matches!(p, Protocol::QuicV1));
+            let mut ip4 = true;
+            *listen_addr = multiaddr
+                .iter()
+                .map(|protocol| match protocol {
+                    Protocol::Ip4(ip) => Protocol::Ip4(overrides.ip.unwrap_or(ip)),
+                    Protocol::Ip6(ip) => {
+                        ip4 = false;
+                        Protocol::Ip6(overrides.ip6.unwrap_or(ip))
+                    }
+                    Protocol::Tcp(port) => {
+                        Protocol::Tcp(overrides.port_field(true, ip4, quic).unwrap_or(port))
+                    }
+                    Protocol::Udp(port) => {
+                        Protocol::Udp(overrides.port_field(false, ip4, quic).unwrap_or(port))
+                    }
+                    protocol => protocol,
+                })
+                .collect::<Multiaddr>()
+                .to_string();
+        }
+        for listen_addr in &overrides.listen_addrs {
+            let Ok(multiaddr) = listen_addr.parse::<Multiaddr>() else {
+                continue;
+            };
+            let quic = multiaddr.iter().any(|p| matches!(p, Protocol::QuicV1));
+            let mut ip4 = true;
+            for protocol in multiaddr.iter() {
+                match protocol {
+                    Protocol::Ip4(_) => self.ip = None,
+                    Protocol::Ip6(_) => {
+                        ip4 = false;
+                        self.ip6 = None
+                    }
+                    Protocol::Tcp(_) => *self.port_field(true, ip4, quic) = None,
+                    Protocol::Udp(_) => *self.port_field(false, ip4, quic) = None,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Overrides the values of this spec with any that are set in `overrides`. Listen addresses are
+    /// replaced as a whole and custom fields are replaced key by key. Addresses and ports in
+    /// `overrides` win over this spec's, whether given as fields or as listen addresses.
+    pub fn merge(mut self, mut overrides: EnrSpec) -> Self {
+        self.override_addresses(&mut overrides);
+        macro_rules! override_fields {
+            ($($field:ident),*) => {
+                $(if overrides.$field.is_some() {
+                    self.$field = overrides.$field;
+                })*
+            };
+        }
+        override_fields!(
+            seq, seq_policy, seq_state, ip, ip6, tcp_port, tcp6_port, udp_port, udp6_port,
+            quic_port, quic6_port, eth2, attnets, syncnets, key_file, key_type
+        );
+        if !overrides.listen_addrs.is_empty() {
+            self.listen_addrs = overrides.listen_addrs;
+        }
+        self.fields.extend(overrides.fields);
+        self
+    }
+
+    /// Collects the IP and port keys, checking the listen addresses against the signing key.
+    pub fn addresses(&self, public_key: &CombinedPublicKey) -> Result<Addresses, &'static str> {
+        let mut addresses = Addresses {
+            ip4: self.ip,
+            ip6: self.ip6,
+            tcp4: self.tcp_port,
+            tcp6: self.tcp6_port,
+            udp4: self.udp_port,
+            udp6: self.udp6_port,
+            quic4: self.quic_port,
+            quic6: self.quic6_port,
+        };
+        let peer_id = public_key.as_peer_id();
+        for listen_addr in &self.listen_addrs {
+            let multiaddr = listen_addr
+                .parse::<Multiaddr>()
+                .map_err(|_| "Invalid listen multiaddr")?;
+            addresses.add_multiaddr(&multiaddr, &peer_id)?;
+        }
+        Ok(addresses)
+    }
+
+    /// Parses the custom fields.
+    pub fn custom_fields(&self) -> Result<Vec<Field>, &'static str> {
+        self.fields
+            .iter()
+            .map(|(key, typed_value)| {
+                let (field_type, value) = typed_value
+                    .split_once(':')
+                    .ok_or("Fields take type:value")?;
+                Field::new(key, field_type, value)
+            })
+            .collect()
+    }
+
+    /// Builds and signs the record. Reserved custom fields are refused unless `allow_reserved` is
+    /// set.
+    pub fn build(&self, key: &CombinedKey, allow_reserved: bool) -> Result<Enr, String> {
+        self.builder(&key.public(), allow_reserved)?
+            .build(key)
+            .map_err(|e| format!("Failed to build ENR: {:?}", e))
+    }
+
+    /// Collects the content of the record for `public_key`, without signing it. Custom fields may
+    /// only set the identity keys with `allow_reserved`, and only those the record keeps.
+    pub fn builder(
+        &self,
+        public_key: &CombinedPublicKey,
+        allow_reserved: bool,
+    ) -> Result<enr::Builder<CombinedKey>, String> {
+        let mut enr_builder = enr::Builder::default();
+        if let Some(seq) = self.seq {
+            enr_builder.seq(seq);
+        }
+
+        self.addresses(public_key)?.apply(&mut enr_builder);
+
+        if let Some(eth2) = &self.eth2 {
+            let eth2_bytes =
+                hex::decode(eth2.trim_start_matches("0x")).map_err(|_| "Invalid eth2 hex bytes")?;
+            EnrForkId::from_ssz_bytes(&eth2_bytes).map_err(|_| "Invalid eth2 ssz bytes")?;
+
+            // The fork id is stored as an RLP byte string.
+            enr_builder.add_value(ETH2_ENR_KEY, &Bytes::from(eth2_bytes));
+        }
+        if let Some(attnets) = &self.attnets {
+            let bitfield = subnet_bitfield(attnets, ATTESTATION_SUBNET_COUNT)?;
+            enr_builder.add_value(BITFIELD_ENR_KEY, &bitfield);
+        }
+        if let Some(syncnets) = &self.syncnets {
+            let bitfield = subnet_bitfield(syncnets, SYNC_COMMITTEE_SUBNET_COUNT)?;
+            enr_builder.add_value(SYNC_COMMITTEE_BITFIELD_ENR_KEY, &bitfield);
+        }
+
+        for field in self.custom_fields()? {
+            if field.is_reserved() && !allow_reserved {
+                return Err(format!(
+                    "Refusing to set the reserved {} key without --force",
+                    field.key
+                ));
+            }
+            // The builder derives `id` and the signer's key, and readers take the record's key
+            // from `secp256k1` before `ed25519`, so only an `ed25519` key beside a secp256k1
+            // signer is kept as given.
+            if field.is_reserved()
+                && !(field.key == "ed25519" && KeyType::of(public_key) == KeyType::Secp256k1)
+            {
+                return Err(format!(
+                    "Cannot force the {} key of a {} record, it is derived from the signing key",
+                    field.key,
+                    KeyType::of(public_key)
+                ));
+            }
+            // Field values are RLP encoded when parsed, `rlp-hex` values are RLP already.
+            enr_builder.add_value_rlp(&field.key, field.rlp);
+        }
+        Ok(enr_builder)
+    }
+}
+
+/// Encodes subnet ids as an SSZ bitvector of `count` bits.
+fn subnet_bitfield(subnets: &[u64], count: u64) -> Result<Bytes, &'static str> {
+    let mut bitfield = vec![0u8; count.div_ceil(8) as usize];
+    for subnet in subnets {
+        if *subnet >= count {
+            return Err("Subnet id out of range");
+        }
+        bitfield[(subnet / 8) as usize] |= 1 << (subnet % 8);
+    }
+    Ok(Bytes::from(bitfield))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth2_ext::Eth2Enr;
+    use enr::EnrPublicKey;
+
+    #[test]
+    fn test_spec_formats_agree() {
+        let toml = r#"
+            seq = 3
+            seq_policy = "explicit"
+            ip = "1.2.3.4"
+            udp_port = 9000
+            listen_addrs = ["/ip4/1.2.3.4/tcp/9000"]
+            attnets = [0, 9]
+
+            [fields]
+            client = "string:enr-cli"
+        "#;
+        let yaml = r#"
+            seq: 3
+            seq_policy: explicit
+            ip: 1.2.3.4
+            udp_port: 9000
+            listen_addrs: ["/ip4/1.2.3.4/tcp/9000"]
+            attnets: [0, 9]
+            fields:
+              client: "string:enr-cli"
+        "#;
+        let from_toml: EnrSpec = toml::from_str(toml).unwrap();
+        let from_yaml: EnrSpec = serde_yaml_ng::from_str(yaml).unwrap();
+        let from_json: EnrSpec =
+            serde_json::from_str(&serde_json::to_string(&from_toml).unwrap()).unwrap();
+        assert_eq!(from_toml, from_yaml);
+        assert_eq!(from_toml, from_json);
+        assert_eq!(from_toml.seq_policy, Some(SeqPolicy::Explicit));
+
+        let enr = from_toml
+            .build(&CombinedKey::generate_secp256k1(), false)
+            .unwrap();
+        assert_eq!(enr.seq(), 3);
+        assert_eq!(enr.udp4(), Some(9000));
+        assert_eq!(enr.tcp4(), Some(9000));
+        assert_eq!(
+            enr.bitfield(),
+            Some(vec![0b0000_0001, 0b0000_0010, 0, 0, 0, 0, 0, 0])
+        );
+
+        let reserved = EnrSpec {
+            fields: [("secp256k1".to_string(), "bytes:00".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(reserved
+            .build(&CombinedKey::generate_secp256k1(), false)
+            .is_err());
+        assert!(reserved
+            .build(&CombinedKey::generate_secp256k1(), true)
+            .is_err());
+
+        let ed25519 = CombinedKey::generate_ed25519().public().encode();
+        let forced = EnrSpec {
+            fields: [(
+                "ed25519".to_string(),
+                format!("bytes:{}", hex::encode(&ed25519)),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let enr = forced
+            .build(&CombinedKey::generate_secp256k1(), true)
+            .unwrap()
+            .to_base64()
+            .parse::<Enr>()
+            .unwrap();
+        assert_eq!(
+            enr.get_decodable::<Bytes>("ed25519"),
+            Some(Ok(ed25519.into()))
+        );
+        assert!(forced
+            .build(&CombinedKey::generate_ed25519(), true)
+            .is_err());
+    }
+
+    #[test]
+    fn test_merge_prefers_overrides() {
+        let file = EnrSpec {
+            ip: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            udp_port: Some(9000),
+            seq_policy: Some(SeqPolicy::Increment),
+            fields: [("a".to_string(), "u64:1".to_string())].into(),
+            ..Default::default()
+        };
+        let cli = EnrSpec {
+            udp_port: Some(9001),
+            seq_policy: Some(SeqPolicy::Timestamp),
+            fields: [("a".to_string(), "u64:2".to_string())].into(),
+            ..Default::default()
+        };
+        let merged = file.merge(cli);
+        assert_eq!(merged.ip, Some(Ipv4Addr::new(1, 1, 1, 1)));
+        assert_eq!(merged.udp_port, Some(9001));
+        assert_eq!(merged.seq_policy, Some(SeqPolicy::Timestamp));
+        assert_eq!(merged.fields["a"], "u64:2");
+    }
+
+    #[test]
+    fn test_command_line_addresses_override_listen_addrs() {
+        let file = EnrSpec {
+            ip: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            listen_addrs: vec![
+                "/ip4/1.2.3.4/udp/9000".into(),
+                "/ip4/1.2.3.4/udp/9001/quic-v1".into(),
+            ],
+            ..Default::default()
+        };
+        let cli = EnrSpec {
+            ip: Some(Ipv4Addr::new(5, 6, 7, 8)),
+            udp_port: Some(9100),
+            ..Default::default()
+        };
+        let enr = file
+            .clone()
+            .merge(cli)
+            .build(&CombinedKey::generate_secp256k1(), false)
+            .unwrap();
+        assert_eq!(enr.ip4(), Some(Ipv4Addr::new(5, 6, 7, 8)));
+        assert_eq!(enr.udp4(), Some(9100));
+        assert_eq!(enr.get_decodable::<u16>("quic"), Some(Ok(9001)));
+
+        let file = EnrSpec {
+            ip: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            ..Default::default()
+        };
+        let cli = EnrSpec {
+            listen_addrs: vec!["/ip4/5.6.7.8/tcp/9000".into()],
+            ..Default::default()
+        };
+        let enr = file
+            .merge(cli)
+            .build(&CombinedKey::generate_secp256k1(), false)
+            .unwrap();
+        assert_eq!(enr.ip4(), Some(Ipv4Addr::new(5, 6, 7, 8)));
+    }
+}
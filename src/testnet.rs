@@ -0,0 +1,414 @@
+//! Generates the node keys, records and bootstrap files of a local test network.
+use crate::derive;
+use crate::enr_ext::EnrExt;
+use crate::eth2_ext::EnrForkId;
+use crate::key_format::ClientFormat;
+use crate::keys::{self, KeyType};
+use crate::spec::EnrSpec;
+use crate::Enr;
+use enr::CombinedKey;
+use sha2::{Digest, Sha256};
+use ssz::Encode;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+/// The forks of a network config, in activation order. Genesis is `GENESIS_FORK_VERSION`.
+const FORKS: [&str; 7] = [
+    "ALTAIR",
+    "BELLATRIX",
+    "CAPELLA",
+    "DENEB",
+    "ELECTRA",
+    "FULU",
+    "GLOAS",
+];
+
+/// From Fulu on, fork digests also depend on the blob schedule.
+const FIRST_BLOB_SCHEDULE_FORK: &str = "FULU";
+
+const FAR_FUTURE_EPOCH: u64 = u64::MAX;
+
+/// The clients `testnet generate` can write key files and flags for.
+pub const CLIENTS: [&str; 3] = ["lighthouse", "prysm", "teku"];
+
+/// A node of the test network.
+struct Node {
+    name: String,
+    ip: Ipv4Addr,
+    port: u16,
+    key_file: PathBuf,
+    enr: Enr,
+}
+
+fn config_str(config: &serde_yaml_ng::Value, key: &str) -> Option<String> {
+    match config.get(key)? {
+        serde_yaml_ng::Value::String(value) => Some(value.clone()),
+        serde_yaml_ng::Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn fork_version(config: &serde_yaml_ng::Value, key: &str) -> Result<[u8; 4], &'static str> {
+    match config.get(key) {
+        // Unquoted versions such as 0x01000000 are read as YAML hex integers.
+        Some(serde_yaml_ng::Value::Number(version)) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .map(u32::to_be_bytes)
+            .ok_or("Invalid fork version in the network config"),
+        Some(serde_yaml_ng::Value::String(version)) => {
+            let bytes = hex::decode(version.trim_start_matches("0x"))
+                .map_err(|_| "Invalid fork version in the network config")?;
+            <[u8; 4]>::try_from(bytes.as_slice()).map_err(|_| "Fork versions must be 4 bytes")
+        }
+        _ => Err("The network config is missing a fork version"),
+    }
+}
+
+/// The epoch and blob limit of the `BLOB_SCHEDULE` entry in force at `epoch`, falling back to the
+/// Electra limit before the first entry.
+fn blob_parameters(config: &serde_yaml_ng::Value, epoch: u64) -> Result<(u64, u64), &'static str> {
+    const INVALID: &str = "Invalid BLOB_SCHEDULE in the network config";
+    let number = |value: &serde_yaml_ng::Value, key: &str| {
+        config_str(value, key).and_then(|number| number.parse::<u64>().ok())
+    };
+    let entries = match config.get("BLOB_SCHEDULE") {
+        Some(serde_yaml_ng::Value::Sequence(entries)) => entries.as_slice(),
+        Some(_) => return Err(INVALID),
+        None => &[],
+    };
+    let mut parameters: Option<(u64, u64)> = None;
+    for entry in entries {
+        let entry_epoch = number(entry, "EPOCH").ok_or(INVALID)?;
+        let max_blobs = number(entry, "MAX_BLOBS_PER_BLOCK").ok_or(INVALID)?;
+        if entry_epoch <= epoch && parameters.is_none_or(|(latest, _)| entry_epoch > latest) {
+            parameters = Some((entry_epoch, max_blobs));
+        }
+    }
+    parameters
+        .or_else(|| {
+            Some((
+                number(config, "ELECTRA_FORK_EPOCH")?,
+                number(config, "MAX_BLOBS_PER_BLOCK_ELECTRA")?,
+            ))
+        })
+        .ok_or("Fork digests from Fulu on need the BLOB_SCHEDULE of the network config")
+}
+
+/// Computes the fork id at genesis for a consensus network config (`config.yaml`).
+pub fn genesis_fork_id(
+    config: &serde_yaml_ng::Value,
+    genesis_validators_root: &[u8; 32],
+) -> Result<EnrForkId, &'static str> {
+    let mut current = ("GENESIS", fork_version(config, "GENESIS_FORK_VERSION")?);
+    let mut next: Option<(u64, [u8; 4])> = None;
+    for fork in FORKS {
+        let Some(epoch) = config_str(config, &format!("{}_FORK_EPOCH", fork)) else {
+            continue;
+        };
+        let epoch = epoch
+            .parse::<u64>()
+            .map_err(|_| "Invalid fork epoch in the network config")?;
+        let version = fork_version(config, &format!("{}_FORK_VERSION", fork))?;
+        if epoch == 0 {
+            current = (fork, version);
+        } else if epoch != FAR_FUTURE_EPOCH && next.is_none_or(|(next_epoch, _)| epoch < next_epoch)
+        {
+            next = Some((epoch, version));
+        }
+    }
+
+    // The fork data root is the hash of the version, padded to a chunk, and the root.
+    let mut fork_data = [0u8; 64];
+    fork_data[..4].copy_from_slice(&current.1);
+    fork_data[32..].copy_from_slice(genesis_validators_root);
+    let mut fork_data_root = Sha256::digest(fork_data);
+    if FORKS
+        .iter()
+        .skip_while(|fork| **fork != FIRST_BLOB_SCHEDULE_FORK)
+        .any(|fork| *fork == current.0)
+    {
+        // From Fulu on, the digest is masked with the hash of the blob parameters at genesis.
+        let (epoch, max_blobs) = blob_parameters(config, 0)?;
+        let mut parameters = [0u8; 16];
+        parameters[..8].copy_from_slice(&epoch.to_le_bytes());
+        parameters[8..].copy_from_slice(&max_blobs.to_le_bytes());
+        for (byte, mask) in fork_data_root.iter_mut().zip(Sha256::digest(parameters)) {
+            *byte ^= mask;
+        }
+    }
+
+    let (next_fork_epoch, next_fork_version) = next.unwrap_or((FAR_FUTURE_EPOCH, current.1));
+    Ok(EnrForkId {
+        fork_digest: <[u8; 4]>::try_from(&fork_data_root[..4]).expect("4 bytes"),
+        next_fork_version,
+        next_fork_epoch,
+    })
+}
+
+/// Reads the genesis validators root from `--genesis-validators-root`, or from the `genesis.ssz`
+/// next to the network config. It follows the genesis time in the SSZ beacon state.
+fn genesis_validators_root(
+    matches: &clap::ArgMatches,
+    config_path: &Path,
+) -> Result<Option<[u8; 32]>, &'static str> {
+    let root = match matches.get_one::<String>("genesis-validators-root") {
+        Some(root) => hex::decode(root.trim_start_matches("0x"))
+            .map_err(|_| "Invalid genesis validators root")?,
+        None => match fs::read(config_path.with_file_name("genesis.ssz")) {
+            Ok(state) if state.len() >= 40 => state[8..40].to_vec(),
+            Ok(_) => return Err("genesis.ssz is too short"),
+            Err(_) => return Ok(None),
+        },
+    };
+    <[u8; 32]>::try_from(root.as_slice())
+        .map(Some)
+        .map_err(|_| "The genesis validators root must be 32 bytes")
+}
+
+/// The `eth2` field shared by all nodes, if it can be determined.
+fn eth2_field(matches: &clap::ArgMatches) -> Result<Option<String>, &'static str> {
+    if let Some(eth2) = matches.get_one::<String>("eth2") {
+        return Ok(Some(eth2.clone()));
+    }
+    let Some(config_path) = matches.get_one::<String>("network-config") else {
+        return Ok(None);
+    };
+    let config_path = Path::new(config_path);
+    let config: serde_yaml_ng::Value = serde_yaml_ng::from_slice(
+        &fs::read(config_path).map_err(|_| "Cannot read the network config")?,
+    )
+    .map_err(|_| "Invalid network config")?;
+    let Some(root) = genesis_validators_root(matches, config_path)? else {
+        println!(
+            "Note: no genesis.ssz or --genesis-validators-root, the records have no eth2 field"
+        );
+        return Ok(None);
+    };
+    let fork_id = genesis_fork_id(&config, &root)?;
+    Ok(Some(format!("0x{}", hex::encode(fork_id.as_ssz_bytes()))))
+}
+
+/// The file a client reads its node key from, and the file the record is written to.
+fn client_files(format: ClientFormat) -> (&'static str, &'static str) {
+    match format {
+        ClientFormat::Lighthouse => ("key.dat", "enr.dat"),
+        ClientFormat::Prysm => ("network-keys", "enr.txt"),
+        _ => ("generated-node-key.dat", "enr.txt"),
+    }
+}
+
+/// The command line flags that start `node` with its key, address and the other nodes as boot
+/// nodes.
+fn client_flags(format: ClientFormat, node: &Node, boot_enrs: &[String]) -> String {
+    let (ip, port, key_file) = (node.ip, node.port, node.key_file.display());
+    let mut flags = match format {
+        ClientFormat::Lighthouse => format!(
+            "--listen-address {ip} --port {port} --enr-address {ip} --enr-udp-port {port} --enr-tcp-port {port} --network-dir {dir}",
+            ip = ip,
+            port = port,
+            dir = node.key_file.parent().expect("key files are in a node directory").display(),
+        ),
+        ClientFormat::Prysm => format!(
+            "--p2p-host-ip {ip} --p2p-tcp-port {port} --p2p-udp-port {port} --p2p-priv-key {key}",
+            ip = ip,
+            port = port,
+            key = key_file
+        ),
+        _ => format!(
+            "--p2p-advertised-ip={ip} --p2p-port={port} --p2p-private-key-file={key}",
+            ip = ip,
+            port = port,
+            key = key_file
+        ),
+    };
+    if !boot_enrs.is_empty() {
+        match format {
+            ClientFormat::Lighthouse => write!(flags, " --boot-nodes {}", boot_enrs.join(",")),
+            ClientFormat::Prysm => boot_enrs
+                .iter()
+                .try_for_each(|enr| write!(flags, " --bootstrap-node {}", enr)),
+            _ => write!(flags, " --p2p-discovery-bootnodes={}", boot_enrs.join(",")),
+        }
+        .expect("writes to a string");
+    }
+    flags
+}
+
+pub fn generate(matches: &clap::ArgMatches) -> Result<(), String> {
+    let count = matches
+        .get_one::<String>("nodes")
+        .expect("Must supply a node count")
+        .parse::<u32>()
+        .map_err(|_| "Invalid node count")?;
+    let ip_base = matches
+        .get_one::<String>("ip-base")
+        .expect("Has a default")
+        .parse::<Ipv4Addr>()
+        .map_err(|_| "Invalid base IPv4 address")?;
+    let port_base = matches
+        .get_one::<String>("port-base")
+        .expect("Has a default")
+        .parse::<u16>()
+        .map_err(|_| "Invalid base port")?;
+    let format = matches
+        .get_one::<String>("client")
+        .expect("Has a default")
+        .parse::<ClientFormat>()?;
+    let key_type = KeyType::from_matches(matches)?.unwrap_or(KeyType::Secp256k1);
+    let seed = matches
+        .get_one::<String>("seed")
+        .map(|seed| derive::parse_seed(seed))
+        .transpose()?;
+    let out_dir = Path::new(matches.get_one::<String>("out-dir").expect("Has a default"));
+    let eth2 = eth2_field(matches)?;
+
+    let mut nodes = Vec::new();
+    for index in 0..count {
+        let ip = u32::from(ip_base)
+            .checked_add(index)
+            .map(Ipv4Addr::from)
+            .ok_or("The node addresses overflow the IPv4 range")?;
+        let port = u16::try_from(u32::from(port_base) + index)
+            .map_err(|_| "The node ports overflow the port range")?;
+        let key: CombinedKey = match &seed {
+            Some(seed) => derive::derive_key(seed, index, key_type),
+            None => key_type.generate(),
+        };
+        let spec = EnrSpec {
+            ip: Some(ip),
+            tcp_port: Some(port),
+            udp_port: Some(port),
+            eth2: eth2.clone(),
+            ..Default::default()
+        };
+        let enr = spec.build(&key, false)?;
+
+        let name = format!("node-{:02}", index);
+        let node_dir = out_dir.join(&name);
+        fs::create_dir_all(&node_dir).map_err(|_| "Cannot create the node directory")?;
+        let (key_file_name, enr_file_name) = client_files(format);
+        let key_file = node_dir.join(key_file_name);
+        keys::write_secret_file(&key_file, &format.encode(&key)?)?;
+        fs::write(node_dir.join(enr_file_name), enr.to_base64())
+            .map_err(|_| "Cannot write ENR file")?;
+
+        nodes.push(Node {
+            name,
+            ip,
+            port,
+            key_file,
+            enr,
+        });
+    }
+
+    let enrs: Vec<String> = nodes.iter().map(|node| node.enr.to_base64()).collect();
+    let mut bootstrap_nodes = String::new();
+    let mut boot_enr_yaml = String::new();
+    for enr in &enrs {
+        writeln!(bootstrap_nodes, "{}", enr).expect("writes to a string");
+        writeln!(boot_enr_yaml, "- {}", enr).expect("writes to a string");
+    }
+    fs::write(out_dir.join("bootstrap_nodes.txt"), bootstrap_nodes)
+        .map_err(|_| "Cannot write bootstrap_nodes.txt")?;
+    fs::write(out_dir.join("boot_enr.yaml"), boot_enr_yaml)
+        .map_err(|_| "Cannot write boot_enr.yaml")?;
+
+    let mut flags = String::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let boot_enrs: Vec<String> = enrs
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, enr)| enr.clone())
+            .collect();
+        writeln!(
+            flags,
+            "# {}\n{}\n",
+            node.name,
+            client_flags(format, node, &boot_enrs)
+        )
+        .expect("writes to a string");
+    }
+    fs::write(out_dir.join("flags.txt"), &flags).map_err(|_| "Cannot write flags.txt")?;
+
+    for node in &nodes {
+        println!(
+            "{} {}:{} NodeId: {} PeerId: {}",
+            node.name,
+            node.ip,
+            node.port,
+            hex::encode(node.enr.node_id().raw()),
+            node.enr.peer_id()
+        );
+    }
+    println!();
+    println!(
+        "Wrote {} nodes, bootstrap_nodes.txt, boot_enr.yaml and flags.txt to {}",
+        nodes.len(),
+        out_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_fork_id() {
+        let config: serde_yaml_ng::Value = serde_yaml_ng::from_str(
+            r#"
+            GENESIS_FORK_VERSION: 0x10000038
+            ALTAIR_FORK_VERSION: "0x20000038"
+            ALTAIR_FORK_EPOCH: 0
+            BELLATRIX_FORK_VERSION: 0x30000038
+            BELLATRIX_FORK_EPOCH: 0
+            CAPELLA_FORK_VERSION: 0x40000038
+            CAPELLA_FORK_EPOCH: 10
+            DENEB_FORK_VERSION: 0x50000038
+            DENEB_FORK_EPOCH: 18446744073709551615
+            "#,
+        )
+        .unwrap();
+        let fork_id = genesis_fork_id(&config, &[0; 32]).unwrap();
+        let mut fork_data = [0u8; 64];
+        fork_data[..4].copy_from_slice(&[0x30, 0, 0, 0x38]);
+        assert_eq!(fork_id.fork_digest[..], Sha256::digest(fork_data)[..4]);
+        assert_eq!(fork_id.next_fork_version, [0x40, 0, 0, 0x38]);
+        assert_eq!(fork_id.next_fork_epoch, 10);
+
+        // The genesis fork digest of mainnet.
+        let mainnet: serde_yaml_ng::Value =
+            serde_yaml_ng::from_str("GENESIS_FORK_VERSION: 0x00000000").unwrap();
+        let mainnet_root =
+            hex::decode("4b363db94e286120d76eb905340fdd4e54bfe9f06bf33ff6cf5ad27f511bfe95")
+                .unwrap();
+        let fork_id =
+            genesis_fork_id(&mainnet, &<[u8; 32]>::try_from(mainnet_root).unwrap()).unwrap();
+        assert_eq!(fork_id.fork_digest, [0xb5, 0x30, 0x3f, 0x2a]);
+
+        let fulu = r#"
+            GENESIS_FORK_VERSION: 0x10000038
+            FULU_FORK_VERSION: 0x70000038
+            FULU_FORK_EPOCH: 0
+        "#;
+        let without_schedule: serde_yaml_ng::Value = serde_yaml_ng::from_str(fulu).unwrap();
+        assert!(genesis_fork_id(&without_schedule, &[0; 32]).is_err());
+        let blob_schedule = r#"
+            BLOB_SCHEDULE:
+              - EPOCH: 0
+                MAX_BLOBS_PER_BLOCK: 12
+              - EPOCH: 5
+                MAX_BLOBS_PER_BLOCK: 15
+        "#;
+        let with_schedule: serde_yaml_ng::Value =
+            serde_yaml_ng::from_str(&format!("{}{}", fulu, blob_schedule)).unwrap();
+        let fork_id = genesis_fork_id(&with_schedule, &[0; 32]).unwrap();
+        // sha256(fork data root) XOR sha256(uint64 0 ++ uint64 12), computed independently.
+        assert_eq!(fork_id.fork_digest, [0xcf, 0xb5, 0x40, 0x25]);
+    }
+}
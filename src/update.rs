@@ -0,0 +1,235 @@
+//! Modifies fields of an existing ENR and re-signs it.
+use crate::enr_ext::{QUIC6_ENR_KEY, QUIC_ENR_KEY};
+use crate::eth2_ext::{EnrForkId, ETH2_ENR_KEY};
+use crate::field::RESERVED_KEYS;
+use crate::keys::{self, KeyType};
+use crate::seq::{self, SeqPolicy};
+use crate::signer::{RemoteSigner, Signer};
+use crate::Enr;
+use bytes::Bytes;
+use enr::{CombinedKey, EnrKey, EnrPublicKey};
+use ssz::Decode;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// The keys holding a port.
+const PORT_KEYS: [&str; 6] = ["tcp", "tcp6", "udp", "udp6", QUIC_ENR_KEY, QUIC6_ENR_KEY];
+
+/// Inserts `key=value` into the record. Known keys are parsed according to their type, other keys
+/// take `0x`-prefixed hex bytes.
+pub fn set_field(
+    enr: &mut Enr,
+    key: &str,
+    value: &str,
+    signing_key: &CombinedKey,
+) -> Result<(), &'static str> {
+    if RESERVED_KEYS.contains(&key) {
+        return Err("Cannot modify the identity keys of a record");
+    }
+    let result = match key {
+        "ip" => {
+            let ip = value
+                .parse::<Ipv4Addr>()
+                .map_err(|_| "Invalid IPv4 address")?;
+            enr.set_ip(IpAddr::V4(ip), signing_key).map(|_| ())
+        }
+        "ip6" => {
+            let ip = value
+                .parse::<Ipv6Addr>()
+                .map_err(|_| "Invalid IPv6 address")?;
+            enr.set_ip(IpAddr::V6(ip), signing_key).map(|_| ())
+        }
+        key if PORT_KEYS.contains(&key) => {
+            let port = value.parse::<u16>().map_err(|_| "Invalid port")?;
+            enr.insert(key, &port, signing_key).map(|_| ())
+        }
+        ETH2_ENR_KEY => {
+            let eth2_bytes = hex::decode(value.trim_start_matches("0x"))
+                .map_err(|_| "Invalid eth2 hex bytes")?;
+            EnrForkId::from_ssz_bytes(&eth2_bytes).map_err(|_| "Invalid eth2 ssz bytes")?;
+            enr.insert(key, &Bytes::from(eth2_bytes), signing_key)
+                .map(|_| ())
+        }
+        _ => {
+            let hex_value = value
+                .strip_prefix("0x")
+                .ok_or("Values of unknown keys must be 0x-prefixed hex bytes")?;
+            let bytes = hex::decode(hex_value).map_err(|_| "Invalid hex bytes")?;
+            enr.insert(key, &Bytes::from(bytes), signing_key)
+                .map(|_| ())
+        }
+    };
+    result.map_err(|_| "Failed to update the record")
+}
+
+/// Removes a key from the record.
+pub fn unset_field(
+    enr: &mut Enr,
+    key: &str,
+    signing_key: &CombinedKey,
+) -> Result<(), &'static str> {
+    if RESERVED_KEYS.contains(&key) {
+        return Err("Cannot modify the identity keys of a record");
+    }
+    enr.remove_key(key, signing_key)
+        .map_err(|_| "Failed to update the record")
+}
+
+/// Renders the value of every key in a record for display.
+pub fn fields(enr: &Enr) -> BTreeMap<String, String> {
+    enr.iter()
+        .map(|(key, raw_rlp)| {
+            let key = String::from_utf8_lossy(key).into_owned();
+            let value = match key.as_str() {
+                "ip" => enr.ip4().map(|ip| ip.to_string()),
+                "ip6" => enr.ip6().map(|ip| ip.to_string()),
+                "id" => enr.id(),
+                key if PORT_KEYS.contains(&key) => enr
+                    .get_decodable::<u16>(key)
+                    .and_then(Result::ok)
+                    .map(|port| port.to_string()),
+                key => enr
+                    .get_decodable::<Bytes>(key)
+                    .and_then(Result::ok)
+                    .map(|bytes| format!("0x{}", hex::encode(bytes))),
+            }
+            // Fall back to the raw RLP for anything that doesn't decode.
+            .unwrap_or_else(|| format!("rlp:0x{}", hex::encode(raw_rlp)));
+            (key, value)
+        })
+        .collect()
+}
+
+/// Prints the changes between two versions of a record.
+pub fn print_diff(old: &Enr, new: &Enr) {
+    println!("seq: {} -> {}", old.seq(), new.seq());
+    let old_fields = fields(old);
+    let new_fields = fields(new);
+    let keys: std::collections::BTreeSet<&String> =
+        old_fields.keys().chain(new_fields.keys()).collect();
+    for key in keys {
+        match (old_fields.get(key), new_fields.get(key)) {
+            (Some(old), Some(new)) if old == new => {}
+            (old, new) => {
+                if let Some(old) = old {
+                    println!("- {}: {}", key, old);
+                }
+                if let Some(new) = new {
+                    println!("+ {}: {}", key, new);
+                }
+            }
+        }
+    }
+}
+
+pub fn update(matches: &clap::ArgMatches) -> Result<(), String> {
+    let old_enr = matches
+        .get_one::<String>("enr")
+        .expect("Must supply an ENR")
+        .parse::<Enr>()
+        .map_err(|_| "Invalid ENR")?;
+    // With a remote signer the changes are made with a throwaway key and the result is signed
+    // remotely.
+    let (key, remote_signer) = match matches.get_one::<String>("signer-url") {
+        Some(signer_url) => {
+            let signer = RemoteSigner::new(signer_url);
+            let public_key = signer.public_key()?;
+            (
+                KeyType::of(&public_key).generate(),
+                Some((signer, public_key)),
+            )
+        }
+        None => (
+            keys::load_key_as(
+                matches,
+                Some(
+                    KeyType::from_matches(matches)?
+                        .unwrap_or_else(|| KeyType::of(&old_enr.public_key())),
+                ),
+            )?
+            .ok_or("A key is required to re-sign the record")?,
+            None,
+        ),
+    };
+    let public_key = match &remote_signer {
+        Some((_, public_key)) => public_key.clone(),
+        None => key.public(),
+    };
+    if public_key.encode() != old_enr.public_key().encode() {
+        return Err("The key does not match the record's public key".into());
+    }
+
+    let mut enr = old_enr.clone();
+    for assignment in matches.get_many::<String>("set").into_iter().flatten() {
+        let (field, value) = assignment.split_once('=').ok_or("--set takes key=value")?;
+        set_field(&mut enr, field, value, &key)?;
+    }
+    for field in matches.get_many::<String>("unset").into_iter().flatten() {
+        unset_field(&mut enr, field, &key)?;
+    }
+    // Each change above bumps the sequence number, the update as a whole should only bump it once.
+    let explicit_seq = matches
+        .get_one::<String>("seq")
+        .map(|seq| seq.parse::<u64>().map_err(|_| "Invalid sequence number"))
+        .transpose()?;
+    let seq_state = matches.get_one::<String>("seq-state").map(Path::new);
+    let seq = seq::next_seq(
+        SeqPolicy::from_matches(matches)?,
+        seq_state,
+        Some(old_enr.seq()),
+        explicit_seq,
+        Some(SeqPolicy::Increment),
+    )?
+    .expect("the default policy applies to a previous record");
+    enr.set_seq(seq, &key)
+        .map_err(|_| "Failed to sign the record")?;
+    if let Some((signer, public_key)) = remote_signer {
+        enr = signer.sign(&enr, &public_key)?;
+    }
+
+    seq::save_state(seq_state, enr.seq())?;
+
+    print_diff(&old_enr, &enr);
+    println!();
+    println!("Updated ENR: {}", enr.to_base64());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_keeps_unknown_keys_and_bumps_seq_once() {
+        let key = CombinedKey::generate_secp256k1();
+        let old = enr::Builder::default()
+            .seq(5)
+            .udp4(9000)
+            .add_value("custom", &Bytes::from_static(b"value"))
+            .add_value(QUIC6_ENR_KEY, &9001u16)
+            .build(&key)
+            .unwrap();
+
+        let mut enr = old.clone();
+        set_field(&mut enr, "udp", "9002", &key).unwrap();
+        set_field(&mut enr, "ip", "1.2.3.4", &key).unwrap();
+        unset_field(&mut enr, QUIC6_ENR_KEY, &key).unwrap();
+        enr.set_seq(old.seq() + 1, &key).unwrap();
+
+        assert_eq!(enr.seq(), 6);
+        assert_eq!(enr.udp4(), Some(9002));
+        assert_eq!(enr.ip4(), Some(Ipv4Addr::new(1, 2, 3, 4)));
+        assert!(enr.get_raw_rlp(QUIC6_ENR_KEY).is_none());
+        assert_eq!(enr.get_raw_rlp("custom"), old.get_raw_rlp("custom"));
+        assert!(enr.verify());
+    }
+
+    #[test]
+    fn test_identity_keys_are_protected() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut enr = enr::Builder::default().build(&key).unwrap();
+        assert!(set_field(&mut enr, "secp256k1", "0x00", &key).is_err());
+        assert!(unset_field(&mut enr, "id", &key).is_err());
+    }
+}